@@ -2,57 +2,207 @@ use minecraft_client_rs::Client;
 use std::env;
 use std::fs;
 use std::io::{self, BufRead};
+use std::process;
 
-//TODO Get these from the arguments instead
-static PASSWORD: &str = "password";
-static ADDRESS: &str = "127.0.0.1:25575";
+static DEFAULT_HOST: &str = "127.0.0.1";
+static DEFAULT_PORT: u16 = 25575;
+
+static USAGE: &str = "Usage: apply-mccmd [OPTIONS] <FILE>
+
+Sends every line of FILE as a command to a Minecraft server, through RCON.
+
+Options:
+  -a, --address <HOST>        Address of the server [env: RCON_HOST] (default: 127.0.0.1)
+  -p, --port <PORT>           RCON port of the server [env: RCON_PORT] (default: 25575)
+      --password-file <PATH>  Read the RCON password from the first line of a file
+  -h, --help                  Print this message
+
+The password is read from --password-file, the RCON_PASSWORD environment variable or,
+if neither is set, prompted for on the terminal (without echoing it).
+
+A command fails when it can't be sent, or when the server rejects it (like an unknown block or
+a fill that is too large). The exit code is non-zero if any command failed.";
+
+struct Config {
+    input: String,
+    host: String,
+    port: u16,
+    password_file: Option<String>,
+}
 
 fn main() {
+    let config = match parse_args() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+
     println!("[info] Parsing commands");
-    let cmds = parse_args();
+    let cmds = match read_commands(&config.input) {
+        Ok(cmds) => cmds,
+        Err(e) => {
+            eprintln!("[error] Could not read {}: {}", config.input, e);
+            process::exit(1);
+        }
+    };
+
+    let password = match read_password(&config) {
+        Ok(password) => password,
+        Err(e) => {
+            eprintln!("[error] Could not read the password: {}", e);
+            process::exit(1);
+        }
+    };
 
-    println!("[info] Connecting to server");
-    let mut client = Client::new(ADDRESS.to_owned()).unwrap();
-    match client.authenticate(PASSWORD.to_owned()) {
+    let address = format!("{}:{}", config.host, config.port);
+    println!("[info] Connecting to server at {}", address);
+    let mut client = match Client::new(address.clone()) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("[error] Could not connect to {}: {}", address, e);
+            process::exit(1);
+        }
+    };
+    match client.authenticate(password) {
         Ok(_) => {
             println!("[info] Connected");
         }
-        Err(_e) => {
-            todo!("handle authentication error");
+        Err(e) => {
+            // The server answers a wrong password with an error response
+            eprintln!(
+                "[error] Authentication failed, is the password right? ({})",
+                e
+            );
+            process::exit(1);
         }
     }
 
     println!("[info] Sending commands");
+    let mut failed = 0;
     for cmd in cmds {
-        match client.send_command(cmd) {
-            Ok(_resp) => {
-                //TODO Log response?
-                // println!("{}", resp.body);
+        match client.send_command(cmd.clone()) {
+            Ok(resp) => {
+                if let Some(error) = command_error(&resp.body) {
+                    eprintln!("[error] Command {:?} was rejected: {}", cmd, error);
+                    failed += 1;
+                }
             }
             Err(e) => {
-                println!("Got error: {:?}", e);
+                eprintln!("[error] Command {:?} failed: {}", cmd, e);
+                failed += 1;
             }
         }
     }
 
     println!("[info] Sent commands, disconnecting from server");
-    client.close().unwrap();
+    if let Err(e) = client.close() {
+        eprintln!("[error] Could not disconnect: {}", e);
+    }
+
+    if failed > 0 {
+        eprintln!("[error] {} command(s) failed", failed);
+        process::exit(1);
+    }
+}
+
+/// The start of the messages Minecraft answers rejected commands with. The reply itself is
+/// successful, so this is the only way of telling. Commands that ran without changing anything
+/// (like filling blocks that are already there) aren't counted as rejected.
+static ERROR_MESSAGES: &[&str] = &[
+    "Unknown or incomplete command",
+    "Incorrect argument for command",
+    "Unknown block type",
+    "Unknown block property",
+    "Expected ",
+    "Invalid ",
+    "Too many blocks in the specified area",
+    "That position is not loaded",
+    "That position is out of this world",
+];
+
+/// The error message in the response to a command, if the server rejected it
+fn command_error(body: &str) -> Option<&str> {
+    let body = body.trim();
+    if ERROR_MESSAGES.iter().any(|m| body.starts_with(m)) || body.contains("<--[HERE]") {
+        Some(body)
+    } else {
+        None
+    }
 }
 
-fn parse_args() -> Vec<String> {
-    let args: Vec<String> = env::args().collect();
-    let input_file_path = args.get(1).expect("No input file.");
+fn parse_args() -> Result<Config, String> {
+    let mut args = env::args().skip(1);
+
+    let mut input = None;
+    let mut host = env::var("RCON_HOST").ok();
+    let mut port = env::var("RCON_PORT").ok();
+    let mut password_file = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("Missing a value for {}", name))
+        };
+
+        match arg.as_str() {
+            "-a" | "--address" => host = Some(value(&arg)?),
+            "-p" | "--port" => port = Some(value(&arg)?),
+            "--password-file" => password_file = Some(value(&arg)?),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            a if a.starts_with('-') => return Err(format!("Unknown option: {}", a)),
+            _ => {
+                if input.is_some() {
+                    return Err(format!("Unexpected argument: {}", arg));
+                }
+                input = Some(arg);
+            }
+        }
+    }
+
+    let port = match port {
+        None => DEFAULT_PORT,
+        Some(p) => p.parse().map_err(|_| format!("Invalid port: {}", p))?,
+    };
+
+    Ok(Config {
+        input: input.ok_or_else(|| "No input file".to_owned())?,
+        host: host.unwrap_or_else(|| DEFAULT_HOST.to_owned()),
+        port,
+        password_file,
+    })
+}
 
-    let file = fs::File::open(input_file_path).unwrap();
+fn read_commands(path: &str) -> io::Result<Vec<String>> {
+    let file = fs::File::open(path)?;
     let reader = io::BufReader::new(file).lines();
 
     let mut out = Vec::new();
     for ln in reader {
-        match ln {
-            Ok(cmd) => out.push(cmd),
-            Err(e) => panic!("Error reading lines: {}", e),
+        let cmd = ln?;
+        if !cmd.trim().is_empty() {
+            out.push(cmd);
         }
     }
 
-    out
+    Ok(out)
+}
+
+/// Reads the password without ever taking it from the arguments, where it would be visible to
+/// every user of the machine.
+fn read_password(config: &Config) -> io::Result<String> {
+    if let Some(path) = &config.password_file {
+        let contents = fs::read_to_string(path)?;
+        return Ok(contents.lines().next().unwrap_or("").to_owned());
+    }
+
+    if let Ok(password) = env::var("RCON_PASSWORD") {
+        return Ok(password);
+    }
+
+    rpassword::prompt_password("RCON password: ")
 }
@@ -0,0 +1,183 @@
+pub const USAGE: &str = "Usage: compiler [OPTIONS] <INPUT>
+
+Options:
+  -b, --backend <BACKEND>   Backend to compile for: scad, mc, structure,
+                            schematic, obj or stl. May be repeated
+                            (default: scad and mc)
+  -o, --output <PATH>       Where to write the output, - for stdout. Needs a single backend
+                            (default: out.scad, out.mccmd, out.nbt, out.schem, out.obj
+                            or out.stl)
+      --delete-output <PATH>
+                            Where to write the commands removing the Minecraft build
+                            (default: del.mccmd)
+  -s, --scale <SCALE>       Number of blocks per unit in Minecraft (default: 10)
+      --origin <X,Y,Z>      Where to place the Minecraft build, in Minecraft's coordinates
+                            where Y is up (default: 0,4,0)
+      --material <BLOCK>    Minecraft block used when nothing else is set (default: birch_planks)
+      --materials <PATH>    Maps material names to Minecraft blocks, colours and MTL materials
+      --ascii-stl           Write STL files as text instead of binary
+      --check               Only parse and evaluate the input, don't write anything
+  -h, --help                Print this message";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    Scad,
+    Mc,
+    /// A Minecraft structure file, for structure blocks
+    Structure,
+    /// A Sponge schematic, for WorldEdit
+    Schematic,
+    Obj,
+    Stl,
+}
+
+impl Backend {
+    pub fn default_output(&self) -> &'static str {
+        match self {
+            Backend::Scad => "out.scad",
+            Backend::Mc => "out.mccmd",
+            Backend::Structure => "out.nbt",
+            Backend::Schematic => "out.schem",
+            Backend::Obj => "out.obj",
+            Backend::Stl => "out.stl",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub input: String,
+    pub backends: Vec<Backend>,
+    /// Overrides the default output path of the (single) backend. `-` means stdout.
+    pub output: Option<String>,
+    pub delete_output: String,
+    pub scale: i32,
+    /// Where to place the Minecraft build, along the axes of the model (where z is up)
+    pub origin: (isize, isize, isize),
+    pub material: String,
+    /// The material mapping file
+    pub materials: Option<String>,
+    pub ascii_stl: bool,
+    pub check: bool,
+    pub help: bool,
+}
+
+impl Config {
+    /// Builds the config from the command line arguments, without the program name
+    pub fn build(mut args: impl Iterator<Item = String>) -> Result<Config, String> {
+        let mut input = None;
+        let mut backends = Vec::new();
+        let mut output = None;
+        let mut delete_output = "del.mccmd".to_owned();
+        let mut scale = 10;
+        let mut origin = (0, 0, 4);
+        let mut material = "birch_planks".to_owned();
+        let mut materials = None;
+        let mut ascii_stl = false;
+        let mut check = false;
+        let mut help = false;
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("Missing a value for {}", name))
+            };
+
+            match arg.as_str() {
+                "-b" | "--backend" => {
+                    let backend = match value(&arg)?.as_str() {
+                        "scad" => Backend::Scad,
+                        "mc" => Backend::Mc,
+                        "structure" => Backend::Structure,
+                        "schematic" => Backend::Schematic,
+                        "obj" => Backend::Obj,
+                        "stl" => Backend::Stl,
+                        b => {
+                            return Err(format!(
+                                "Unknown backend: {} (expected scad, mc, structure, schematic, obj or stl)",
+                                b
+                            ))
+                        }
+                    };
+                    if !backends.contains(&backend) {
+                        backends.push(backend);
+                    }
+                }
+                "-o" | "--output" => output = Some(value(&arg)?),
+                "--delete-output" => delete_output = value(&arg)?,
+                "-s" | "--scale" => {
+                    let v = value(&arg)?;
+                    scale = match v.parse() {
+                        Ok(s) if s > 0 => s,
+                        _ => {
+                            return Err(format!(
+                                "Invalid scale: {} (expected a positive integer)",
+                                v
+                            ))
+                        }
+                    };
+                }
+                "--origin" => origin = parse_origin(&value(&arg)?)?,
+                "--material" => material = value(&arg)?,
+                "--materials" => materials = Some(value(&arg)?),
+                "--ascii-stl" => ascii_stl = true,
+                "--check" => check = true,
+                "-h" | "--help" => help = true,
+                a if a.starts_with('-') && a != "-" => {
+                    return Err(format!("Unknown option: {}", a))
+                }
+                _ => {
+                    if input.is_some() {
+                        return Err(format!("Unexpected argument: {}", arg));
+                    }
+                    input = Some(arg);
+                }
+            }
+        }
+
+        if backends.is_empty() {
+            backends = vec![Backend::Scad, Backend::Mc];
+        }
+
+        if output.is_some() && backends.len() != 1 {
+            return Err("--output can only be used with a single --backend".to_owned());
+        }
+
+        let input = match input {
+            Some(input) => input,
+            None if help => String::new(),
+            None => return Err("No input file".to_owned()),
+        };
+
+        Ok(Config {
+            input,
+            backends,
+            output,
+            delete_output,
+            scale,
+            origin,
+            material,
+            materials,
+            ascii_stl,
+            check,
+            help,
+        })
+    }
+
+    pub fn output_of(&self, backend: Backend) -> &str {
+        match &self.output {
+            Some(o) => o,
+            None => backend.default_output(),
+        }
+    }
+}
+
+/// Parses an origin given in Minecraft's coordinates, where y is up, into the axes of the model
+fn parse_origin(v: &str) -> Result<(isize, isize, isize), String> {
+    let parts: Vec<Result<isize, _>> = v.split(',').map(|p| p.trim().parse()).collect();
+
+    match parts.as_slice() {
+        [Ok(x), Ok(y), Ok(z)] => Ok((*x, *z, *y)),
+        _ => Err(format!("Invalid origin: {} (expected X,Y,Z)", v)),
+    }
+}
@@ -0,0 +1,37 @@
+use super::lex::Span;
+
+/// Formats an error the way rustc does, with the offending source code underlined:
+///
+/// ```text
+/// error: Unknown variable `heigth`
+///  --> resources/cube.cap:2:10
+///   |
+/// 2 | (extrude heigth
+///   |          ^^^^^^
+/// ```
+pub fn render(path: &str, src: &str, message: &str, span: Span) -> String {
+    let line = src.lines().nth(span.line - 1).unwrap_or("");
+    let line_number = span.line.to_string();
+    let gutter = " ".repeat(line_number.len());
+
+    // Spans over several lines are underlined to the end of their first line
+    let end_column = if span.end_line == span.line {
+        span.end_column
+    } else {
+        line.chars().count() + 1
+    };
+    let underline_len = end_column.saturating_sub(span.column).max(1);
+
+    format!(
+        "error: {message}\n{gutter}--> {path}:{line}:{column}\n{gutter} |\n{line_number} | {src}\n{gutter} | {pad}{underline}",
+        message = message,
+        gutter = gutter,
+        path = path,
+        line = span.line,
+        column = span.column,
+        line_number = line_number,
+        src = line,
+        pad = " ".repeat(span.column - 1),
+        underline = "^".repeat(underline_len),
+    )
+}
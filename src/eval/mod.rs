@@ -1,20 +1,81 @@
 use std::collections::HashMap;
 use std::fmt;
 
+use super::lex::Span;
 use super::parse;
 
+mod offset;
+pub mod plane;
+mod transform;
 mod util;
 
+pub use transform::Transform;
+
 #[derive(Debug)]
 pub enum EvalError {
   UnexpectedExpression(parse::Value),
+  UnknownVariable(String, Span),
+  /// A function was called with too few or too many arguments
+  ArgumentCount {
+    function: String,
+    expected: String,
+    got: usize,
+    span: Span,
+  },
+  /// A value of the wrong kind, like a polygon where a number was expected
+  UnexpectedValue {
+    expected: String,
+    /// Boxed, since native values can be large
+    got: Box<EvaluatedValue>,
+    span: Span,
+  },
+  /// Any other invalid argument, described by the message
+  InvalidArgument(String, Span),
+}
+
+impl EvalError {
+  pub fn span(&self) -> Span {
+    match self {
+      EvalError::UnexpectedExpression(v) => v.span(),
+      EvalError::UnknownVariable(_, span) => *span,
+      EvalError::ArgumentCount { span, .. } => *span,
+      EvalError::UnexpectedValue { span, .. } => *span,
+      EvalError::InvalidArgument(_, span) => *span,
+    }
+  }
+}
+
+impl fmt::Display for EvalError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      EvalError::UnexpectedExpression(_) => write!(f, "Unexpected expression"),
+      EvalError::UnknownVariable(name, _) => write!(f, "Unknown variable `{}`", name),
+      EvalError::ArgumentCount {
+        function,
+        expected,
+        got,
+        ..
+      } => write!(
+        f,
+        "`{}` takes {} argument(s), but got {}",
+        function, expected, got
+      ),
+      EvalError::UnexpectedValue { expected, got, .. } => {
+        write!(f, "Expected {}, got {}", expected, got.describe())
+      }
+      EvalError::InvalidArgument(message, _) => write!(f, "{}", message),
+    }
+  }
 }
 
 //TODO Add optional description/documentation string
 #[derive(Clone)]
 enum EvalFunction {
   LispFunction(LispFunction),
-  NativeFunction(fn(&mut EvalEnvironment, &[parse::Value]) -> Result<EvaluatedValue, EvalError>),
+  /// Native functions get the span of the whole call, and their (unevaluated) arguments
+  NativeFunction(
+    fn(&mut EvalEnvironment, Span, &[parse::Value]) -> Result<EvaluatedValue, EvalError>,
+  ),
 }
 
 impl fmt::Debug for EvalFunction {
@@ -26,23 +87,90 @@ impl fmt::Debug for EvalFunction {
   }
 }
 
-#[derive(Debug, Clone)]
+/// A function written in lisp. Like in most lisps, its body only sees its arguments and the
+/// variables that could be seen where it was defined, not the ones of whoever calls it.
+#[derive(Debug, Clone, PartialEq)]
 struct LispFunction {
   arguments: Vec<(String, Option<parse::Value>)>,
   body: Vec<parse::Value>,
+  /// The local variables where the function was defined. They are copied, so changing them later
+  /// doesn't change what the function sees, and the function can't `set` them.
+  captured: HashMap<String, EvaluatedValue>,
 }
 
 impl LispFunction {
-  pub fn new(arguments: Vec<(String, Option<parse::Value>)>, body: Vec<parse::Value>) -> Self {
-    Self { arguments, body }
+  pub fn new(
+    arguments: Vec<(String, Option<parse::Value>)>,
+    body: Vec<parse::Value>,
+    captured: HashMap<String, EvaluatedValue>,
+  ) -> Self {
+    Self {
+      arguments,
+      body,
+      captured,
+    }
+  }
+
+  /// Calls the function with (evaluated) arguments, evaluating the defaults of the ones left out
+  fn call(
+    &self,
+    env: &mut EvalEnvironment,
+    name: &str,
+    span: Span,
+    args: Vec<EvaluatedValue>,
+  ) -> Result<EvaluatedValue, EvalError> {
+    let caller = std::mem::take(&mut env.locals);
+    let caller_captured = std::mem::replace(&mut env.captured, self.captured.clone());
+    env.push_defs();
+    let rv = self.call_body(env, name, span, args);
+    env.locals = caller;
+    env.captured = caller_captured;
+
+    rv
+  }
+
+  fn call_body(
+    &self,
+    env: &mut EvalEnvironment,
+    name: &str,
+    span: Span,
+    args: Vec<EvaluatedValue>,
+  ) -> Result<EvaluatedValue, EvalError> {
+    let required = self.arguments.iter().filter(|a| a.1.is_none()).count();
+    util::expect_count(name, span, args.len(), required, Some(self.arguments.len()))?;
+
+    let mut args = args.into_iter();
+    for arg in self.arguments.iter() {
+      let value = match (args.next(), &arg.1) {
+        (Some(v), _) => v,
+        (None, Some(default)) => evaluate_expression(default, env)?,
+        (None, None) => unreachable!("the number of arguments is checked above"),
+      };
+      env.set_local(arg.0.to_owned(), value)
+    }
+
+    let mut out = EvaluatedValue::None;
+    for expr in &self.body {
+      out = evaluate_expression(expr, env)?;
+    }
+
+    Ok(out)
   }
 }
 
+/// A function made by `lambda`, which can be passed around like any other value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lambda(LispFunction);
+
 #[derive(Debug, Clone)]
 struct EvalEnvironment {
   functions: HashMap<String, EvalFunction>,
   variables: HashMap<String, EvaluatedValue>,
   locals: Vec<HashMap<String, EvaluatedValue>>,
+  /// The variables captured by the function that is being called (see `LispFunction`)
+  captured: HashMap<String, EvaluatedValue>,
+  /// The transforms (like translate) whose body is currently being evaluated, outermost first
+  transforms: Vec<Transform>,
 }
 
 impl Default for EvalEnvironment {
@@ -51,6 +179,8 @@ impl Default for EvalEnvironment {
       functions: HashMap::new(),
       variables: HashMap::new(),
       locals: Vec::new(),
+      captured: HashMap::new(),
+      transforms: Vec::new(),
     };
 
     env.set_variable("nil".to_owned(), EvaluatedValue::None);
@@ -58,7 +188,7 @@ impl Default for EvalEnvironment {
 
     env.functions.insert(
       "print".to_owned(),
-      EvalFunction::NativeFunction(|env, args| {
+      EvalFunction::NativeFunction(|env, _span, args| {
         let mut out = Vec::new();
         for a in args {
           out.push(format!("{:?}", evaluate_expression(a, env)?));
@@ -72,80 +202,46 @@ impl Default for EvalEnvironment {
 
     env.functions.insert(
       "+".to_owned(),
-      EvalFunction::NativeFunction(|env, args| match args.get(0) {
-        None => unimplemented!("+ needs some arguments"),
-        Some(v) => match evaluate_expression(v, env)? {
-          EvaluatedValue::Vector(_vector) => {
-            panic!("+ function does not support vectors. Consider v+ and vv+");
-          }
-          v => {
-            let mut sum = 0.0;
-            sum += util::cast_number(&v);
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("+", span, args, 1, None)?;
 
-            for a in &args[1..] {
-              let a = evaluate_expression(a, env)?;
-              sum += util::cast_number(&a);
-            }
+        let mut sum = 0.0;
+        for a in args {
+          sum += util::evaluate_number(env, a)?;
+        }
 
-            Ok(EvaluatedValue::Number(sum))
-          }
-        },
+        Ok(EvaluatedValue::Number(sum))
       }),
     );
 
     env.functions.insert(
       "-".to_owned(),
-      EvalFunction::NativeFunction(|env, args| match args.get(0) {
-        None => unimplemented!("- needs some arguments"),
-        Some(v) => match evaluate_expression(v, env)? {
-          EvaluatedValue::Vector(_vector) => {
-            panic!("- function does not support vectors. Consider v- and vv-");
-          }
-          v => {
-            //TODO Rename sum :P
-            let mut sum = 0.0;
-            sum += util::cast_number(&v);
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("-", span, args, 1, None)?;
 
-            for a in &args[1..] {
-              let a = evaluate_expression(a, env)?;
-              sum -= util::cast_number(&a);
-            }
+        //TODO Rename sum :P
+        let mut sum = util::evaluate_number(env, &args[0])?;
+        for a in &args[1..] {
+          sum -= util::evaluate_number(env, a)?;
+        }
 
-            if args.len() == 1 {
-              sum = -sum;
-            }
+        if args.len() == 1 {
+          sum = -sum;
+        }
 
-            Ok(EvaluatedValue::Number(sum))
-          }
-        },
+        Ok(EvaluatedValue::Number(sum))
       }),
     );
 
     env.functions.insert(
       "?".to_owned(),
-      EvalFunction::NativeFunction(|env, args| {
-        if args.len() != 2 && args.len() > 3 {
-          unimplemented!("Please, pass a condition, a handler and maybe an else. No more for now.");
-        }
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("?", span, args, 2, Some(3))?;
 
-        let condition = match args.first() {
-          None => unreachable!(),
-          Some(v) => match evaluate_expression(v, env)? {
-            EvaluatedValue::None => false,
-            EvaluatedValue::True => true,
-            EvaluatedValue::Number(n) => n != 0.0,
-            EvaluatedValue::Vector(_) => true,
-            EvaluatedValue::Native(_) => {
-              unimplemented!("native value (like polygon or extrude)")
-            }
-          },
-        };
+        let condition = util::cast_condition(&evaluate_expression(&args[0], env)?, args[0].span())?;
 
         Ok(if condition {
-          match args.get(1) {
-            None => unreachable!("No if-handler"),
-            Some(v) => evaluate_expression(v, env)?,
-          }
+          evaluate_expression(&args[1], env)?
         } else {
           match args.get(2) {
             None => EvaluatedValue::None,
@@ -157,54 +253,43 @@ impl Default for EvalEnvironment {
 
     env.functions.insert(
       "==".to_owned(),
-      EvalFunction::NativeFunction(|env, args| {
-        if args.len() != 2 {
-          unimplemented!("Please, pass a 2 values to the '=='-function. No more for now.");
-        }
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("==", span, args, 2, Some(2))?;
 
+        let incomparable = |v: EvaluatedValue, span: Span| EvalError::UnexpectedValue {
+          expected: "a number, vector, nil or t".to_owned(),
+          got: Box::new(v),
+          span,
+        };
+
+        let arg2_span = args[1].span();
+        let arg2 = evaluate_expression(&args[1], env)?;
         let is_true = |a| match a {
-          EvaluatedValue::None => false,
-          EvaluatedValue::True => true,
-          EvaluatedValue::Number(n) => n != 0.0,
-          EvaluatedValue::Vector(v) => v.len() != 0,
-          EvaluatedValue::Native(_) => {
-            unimplemented!("native value (like polygon or extrude)")
-          }
+          EvaluatedValue::None => Ok(false),
+          EvaluatedValue::True => Ok(true),
+          EvaluatedValue::Number(n) => Ok(n != 0.0),
+          EvaluatedValue::Vector(v) => Ok(!v.is_empty()),
+          v => Err(incomparable(v, arg2_span)),
         };
 
-        let res = match args.first() {
-          None => unreachable!(),
-          Some(v) => {
-            let arg2 = match args.get(1) {
-              None => unreachable!(),
-              Some(v) => evaluate_expression(v, env)?,
-            };
-            match evaluate_expression(v, env)? {
-              EvaluatedValue::None => !is_true(arg2),
-              EvaluatedValue::True => is_true(arg2),
-              EvaluatedValue::Number(n) => match arg2 {
-                EvaluatedValue::None => n == 0.0,
-                EvaluatedValue::True => n != 0.0,
-                EvaluatedValue::Number(n1) => n == n1,
-                EvaluatedValue::Vector(v) => v.len() == 0 && n == 0.0,
-                EvaluatedValue::Native(_) => {
-                  unimplemented!("native value (like polygon or extrude)")
-                }
-              },
-              EvaluatedValue::Vector(v1) => match arg2 {
-                EvaluatedValue::None => v1.len() == 0,
-                EvaluatedValue::True => v1.len() != 0,
-                EvaluatedValue::Number(n) => n == 0.0 && v1.len() == 0,
-                EvaluatedValue::Vector(v2) => v1 == v2,
-                EvaluatedValue::Native(_) => {
-                  unimplemented!("native value (like polygon or extrude)")
-                }
-              },
-              EvaluatedValue::Native(_) => {
-                unimplemented!("native value (like polygon or extrude)")
-              }
-            }
-          }
+        let res = match evaluate_expression(&args[0], env)? {
+          EvaluatedValue::None => !is_true(arg2)?,
+          EvaluatedValue::True => is_true(arg2)?,
+          EvaluatedValue::Number(n) => match arg2 {
+            EvaluatedValue::None => n == 0.0,
+            EvaluatedValue::True => n != 0.0,
+            EvaluatedValue::Number(n1) => n == n1,
+            EvaluatedValue::Vector(v) => v.is_empty() && n == 0.0,
+            v => return Err(incomparable(v, arg2_span)),
+          },
+          EvaluatedValue::Vector(v1) => match arg2 {
+            EvaluatedValue::None => v1.is_empty(),
+            EvaluatedValue::True => !v1.is_empty(),
+            EvaluatedValue::Number(n) => n == 0.0 && v1.is_empty(),
+            EvaluatedValue::Vector(v2) => v1 == v2,
+            v => return Err(incomparable(v, arg2_span)),
+          },
+          v => return Err(incomparable(v, args[0].span())),
         };
 
         Ok(if res {
@@ -217,156 +302,724 @@ impl Default for EvalEnvironment {
 
     env.functions.insert(
       "nth".to_owned(),
-      EvalFunction::NativeFunction(|env, args| {
-        if args.len() != 2 {
-          panic!("nth function needs two arguments. First is index, second is the vector.");
-        }
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("nth", span, args, 2, Some(2))?;
 
-        Ok(match args.first() {
-          None => unreachable!(),
-          Some(v) => match evaluate_expression(v, env)? {
-            EvaluatedValue::Number(n) => {
-              let idx = n.floor() as usize;
-
-              match args.get(1) {
-                None => unreachable!(),
-                Some(v) => match evaluate_expression(v, env)? {
-                  EvaluatedValue::Vector(v) => match v.get(idx) {
-                    None => EvaluatedValue::None,
-                    Some(v) => v.clone(),
-                  },
-                  _ => todo!(),
-                },
-              }
+        let idx = match evaluate_expression(&args[0], env)? {
+          EvaluatedValue::Number(n) => n,
+          v => {
+            return Err(EvalError::UnexpectedValue {
+              expected: "a number (index)".to_owned(),
+              got: Box::new(v),
+              span: args[0].span(),
+            })
+          }
+        };
+
+        Ok(match evaluate_expression(&args[1], env)? {
+          EvaluatedValue::Vector(v) => {
+            if idx < 0.0 || idx >= v.len() as f64 || idx.fract() != 0.0 {
+              return Err(EvalError::InvalidArgument(
+                format!(
+                  "Expected a whole number index below {}, got {}",
+                  v.len(),
+                  idx
+                ),
+                args[0].span(),
+              ));
             }
-            _ => todo!("'nth' index must be a number"),
-          },
+            v[idx as usize].clone()
+          }
+          v => {
+            return Err(EvalError::UnexpectedValue {
+              expected: "a vector".to_owned(),
+              got: Box::new(v),
+              span: args[1].span(),
+            })
+          }
         })
       }),
     );
 
-    env.set_variable(
-      "CURRENT_POSITION".to_owned(),
-      EvaluatedValue::Vector(vec![
-        EvaluatedValue::Number(0.0),
-        EvaluatedValue::Number(0.0),
-        EvaluatedValue::Number(0.0),
-      ]),
+    env.functions.insert(
+      "funcall".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("funcall", span, args, 1, None)?;
+
+        let f = evaluate_lambda(env, &args[0])?;
+        let mut values = Vec::new();
+        for a in &args[1..] {
+          values.push(evaluate_expression(a, env)?);
+        }
+
+        f.0.call(env, "lambda", span, values)
+      }),
     );
+
+    env.functions.insert(
+      "apply".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("apply", span, args, 2, Some(2))?;
+
+        let f = evaluate_lambda(env, &args[0])?;
+        let values = match evaluate_expression(&args[1], env)? {
+          EvaluatedValue::Vector(v) => v,
+          EvaluatedValue::None => Vec::new(),
+          v => {
+            return Err(EvalError::UnexpectedValue {
+              expected: "a vector of arguments".to_owned(),
+              got: Box::new(v),
+              span: args[1].span(),
+            })
+          }
+        };
+
+        f.0.call(env, "lambda", span, values)
+      }),
+    );
+
     env.functions.insert(
       "polygon".to_owned(),
-      EvalFunction::NativeFunction(|env, args| {
-        if args.len() != 1 && args.len() != 2 {
-          unimplemented!("make-polygon: Please, pass a list of points and maybe a list of the order of these points. No more for now.");
-        }
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("polygon", span, args, 1, Some(2))?;
+
+        let points_span = args[0].span();
+        let points = match evaluate_expression(&args[0], env)? {
+          EvaluatedValue::Vector(values) => {
+            if values.len() < 3 {
+              return Err(EvalError::InvalidArgument(
+                format!(
+                  "A polygon needs at least three points, got {}",
+                  values.len()
+                ),
+                points_span,
+              ));
+            }
 
-        let cur_pos = match env.get_variable("CURRENT_POSITION") {
-          Some(EvaluatedValue::Vector(v)) => {
-            if !v.len() == 3 {
-              unimplemented!("CURRENT_POSITION has to be a 3D-vector. Got a {}D-vector", v.len())
+            let mut points: Vec<(f64, f64)> = Vec::new();
+            for v in values {
+              points.push(match v {
+                EvaluatedValue::Vector(values) if values.len() == 2 => (
+                  util::cast_number(&values[0], points_span)?,
+                  util::cast_number(&values[1], points_span)?,
+                ),
+                got => {
+                  return Err(EvalError::UnexpectedValue {
+                    expected: "a point (2D-vector)".to_owned(),
+                    got: Box::new(got),
+                    span: points_span,
+                  })
+                }
+              });
             }
 
-            let mut out = Vec::new();
-            for item in v {
-              match item {
-                EvaluatedValue::Number(n)=>out.push(*n),
-                _ => unimplemented!("CURRENT_POSITION has to be a 3D-vector of numbers. Got {:?}", v),
+            points
+          }
+          got => {
+            return Err(EvalError::UnexpectedValue {
+              expected: "a vector of points".to_owned(),
+              got: Box::new(got),
+              span: points_span,
+            })
+          }
+        };
+
+        let paths = match args.get(1) {
+          None => None,
+          Some(v) => Some(evaluate_paths(env, v, points.len())?),
+        };
+
+        Ok(EvaluatedValue::Native(NativeValue::Polygon(Polygon(
+          points, paths, None,
+        ))))
+      }),
+    );
+
+    env.functions.insert(
+      "circle".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("circle", span, args, 1, Some(2))?;
+
+        let r = evaluate_positive(env, &args[0], "radius")?;
+        let segments = match args.get(1) {
+          None => circle_segments(r),
+          Some(v) => evaluate_count(env, v, 3)?,
+        };
+
+        Ok(EvaluatedValue::Native(NativeValue::Polygon(Polygon(
+          circle_points(segments, r),
+          None,
+          Some(Shape::Circle(r, segments)),
+        ))))
+      }),
+    );
+
+    env.functions.insert(
+      "square".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("square", span, args, 1, Some(2))?;
+
+        // A single number makes a square, and a 2D-vector a rectangle
+        let size_span = args[0].span();
+        let size = match evaluate_expression(&args[0], env)? {
+          n @ EvaluatedValue::Number(_) => {
+            let n = util::cast_number(&n, size_span)?;
+            (n, n)
+          }
+          EvaluatedValue::Vector(v) if v.len() == 2 => (
+            util::cast_number(&v[0], size_span)?,
+            util::cast_number(&v[1], size_span)?,
+          ),
+          got => {
+            return Err(EvalError::UnexpectedValue {
+              expected: "a size (number or 2D-vector)".to_owned(),
+              got: Box::new(got),
+              span: size_span,
+            })
+          }
+        };
+        if size.0 <= 0.0 || size.1 <= 0.0 {
+          return Err(EvalError::InvalidArgument(
+            format!("The size needs to be positive, got ({} {})", size.0, size.1),
+            size_span,
+          ));
+        }
+
+        let center = match args.get(1) {
+          None => false,
+          Some(v) => util::cast_condition(&evaluate_expression(v, env)?, v.span())?,
+        };
+
+        Ok(EvaluatedValue::Native(rectangle(size, center)))
+      }),
+    );
+
+    env.functions.insert(
+      "rect".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("rect", span, args, 2, Some(3))?;
+
+        let size = (
+          evaluate_positive(env, &args[0], "width")?,
+          evaluate_positive(env, &args[1], "height")?,
+        );
+        let center = match args.get(2) {
+          None => false,
+          Some(v) => util::cast_condition(&evaluate_expression(v, env)?, v.span())?,
+        };
+
+        Ok(EvaluatedValue::Native(rectangle(size, center)))
+      }),
+    );
+
+    env.functions.insert(
+      "ngon".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("ngon", span, args, 2, Some(2))?;
+
+        let sides = evaluate_count(env, &args[0], 3)?;
+        let r = evaluate_positive(env, &args[1], "radius")?;
+
+        Ok(EvaluatedValue::Native(NativeValue::Polygon(Polygon(
+          circle_points(sides, r),
+          None,
+          Some(Shape::Ngon(sides, r)),
+        ))))
+      }),
+    );
+
+    env.functions.insert(
+      "offset".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        let (args, keywords) = util::split_keywords("offset", args, &["join", "segments"])?;
+        util::expect_args("offset", span, &args, 2, Some(2))?;
+
+        let delta = util::evaluate_number(env, &args[0])?;
+        let p = match evaluate_expression(&args[1], env)? {
+          EvaluatedValue::Native(NativeValue::Polygon(p)) => p,
+          got => {
+            return Err(EvalError::UnexpectedValue {
+              expected: "a polygon".to_owned(),
+              got: Box::new(got),
+              span: args[1].span(),
+            })
+          }
+        };
+
+        // Like the name of a material, the kind of join is used as is
+        let join = match keywords.get("join") {
+          None => "round",
+          Some(parse::Value::Identifier(name, _)) => name.as_str(),
+          Some(v) => {
+            return Err(EvalError::InvalidArgument(
+              "Expected round, miter or chamfer".to_owned(),
+              v.span(),
+            ))
+          }
+        };
+        let join = match join {
+          "round" => Join::Round(match keywords.get("segments") {
+            None => circle_segments(delta.abs()),
+            Some(v) => evaluate_count(env, v, 3)?,
+          }),
+          "miter" => Join::Miter,
+          "chamfer" => Join::Chamfer,
+          _ => {
+            return Err(EvalError::InvalidArgument(
+              format!("Expected round, miter or chamfer, got {}", join),
+              keywords["join"].span(),
+            ))
+          }
+        };
+
+        if delta == 0.0 {
+          return Ok(EvaluatedValue::Native(NativeValue::Polygon(p)));
+        }
+
+        let (points, paths) = offset::offset(&p, delta, &join);
+        Ok(EvaluatedValue::Native(NativeValue::Polygon(Polygon(
+          points,
+          Some(paths),
+          Some(Shape::Offset(Box::new(p), delta, join)),
+        ))))
+      }),
+    );
+
+    env.functions.insert(
+      "extrude".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        let (args, keywords) =
+          util::split_keywords("extrude", args, &["twist", "top-scale", "slices", "center"])?;
+        util::expect_args("extrude", span, &args, 2, Some(2))?;
+
+        let height = evaluate_positive(env, &args[0], "height")?;
+        let profile = evaluate_flat(env, &args[1])?;
+
+        let twist = match keywords.get("twist") {
+          None => 0.0,
+          Some(v) => util::evaluate_number(env, v)?,
+        };
+
+        // A single number scales uniformly, and a 2D-vector scales x and y separately
+        let top_scale = match keywords.get("top-scale") {
+          None => (1.0, 1.0),
+          Some(v) => {
+            let top_scale = match evaluate_expression(v, env)? {
+              n @ EvaluatedValue::Number(_) => {
+                let n = util::cast_number(&n, v.span())?;
+                (n, n)
+              }
+              EvaluatedValue::Vector(f) if f.len() == 2 => (
+                util::cast_number(&f[0], v.span())?,
+                util::cast_number(&f[1], v.span())?,
+              ),
+              got => {
+                return Err(EvalError::UnexpectedValue {
+                  expected: "a scale (number or 2D-vector)".to_owned(),
+                  got: Box::new(got),
+                  span: v.span(),
+                })
               }
+            };
+            if top_scale.0 < 0.0 || top_scale.1 < 0.0 {
+              return Err(EvalError::InvalidArgument(
+                "The top can't be scaled by a negative factor".to_owned(),
+                v.span(),
+              ));
             }
+            top_scale
+          }
+        };
 
-            (out[0], out[1], out[2])
-          },
-          v => unimplemented!("CURRENT_POSITION has to be a 3D-vector. Got {:?}", v),
+        let slices = match keywords.get("slices") {
+          None => None,
+          Some(v) => Some(evaluate_count(env, v, 1)?),
         };
 
-        let order = match args.get(1) {
-            None => None,
-            Some(v) => match evaluate_expression(v, env)? {
-              EvaluatedValue::Vector(values) => {
-                //TODO Make sure all of the values are floats
-                let mut order = Vec::new();
-                for v in values {
-                  order.push(match v {
-                    EvaluatedValue::Number(n) => n.floor() as usize,
-                    _ => unimplemented!("Expected numbers in order (order of polygons)"),
-                  });
-                }
+        let center = match keywords.get("center") {
+          None => false,
+          Some(v) => util::cast_condition(&evaluate_expression(v, env)?, v.span())?,
+        };
 
-                Some(order)
-              }
-              _ => unimplemented!("Expected a vector (order of polygon)"),
+        let extrude = |p: Polygon| {
+          // Like OpenSCAD, twisted extrusions get as many slices as a circle around the polygon
+          // would have segments for the angle
+          let slices = match slices {
+            None if twist != 0.0 => {
+              let r = p
+                .0
+                .iter()
+                .map(|q| (q.0 * q.0 + q.1 * q.1).sqrt())
+                .fold(0.0, f64::max);
+              ((circle_segments(r) as f64 * twist.abs() / 360.0).ceil() as usize)
+                .clamp(1, MAX_COUNT)
             }
+            None => 1,
+            Some(slices) => slices,
           };
 
-        let points = match args.get(0) {
-          None => unimplemented!("Polygon needs its points"),
-          Some(v) => match evaluate_expression(v, env)? {
-            EvaluatedValue::Vector(values) => {
-              if values.len() < 3 {
-                panic!("Polygon needs at least three values");
-              }
+          Ok(NativeValue::Extrude(
+            p,
+            Extrusion {
+              height,
+              twist,
+              top_scale,
+              slices,
+              center,
+            },
+          ))
+        };
 
-              let mut points: Vec<(f64, f64)> = Vec::new();
-              for v in values {
-                points.push(match v {
-                  EvaluatedValue::Vector(values) => {
-                    let x: f64 = match values.get(0) {
-                      None => unimplemented!("A point has two values (in a polygon). Got none."),
-                      Some(val) => match val {
-                        EvaluatedValue::Number(val) => *val,
-                        _ => unimplemented!("A point is a vector of NUMBERS (got {:?})", values),
-                      }
-                    };
-                    let y: f64 = match values.get(1) {
-                      None => unimplemented!("A point has two values (in a polygon). Got one."),
-                      Some(v) => match v {
-                        EvaluatedValue::Number(v) => *v,
-                        _ => unimplemented!("A point is a vector of NUMBERS"),
-                      }
-                    };
-
-                    (x, y)
-                  }
-                  _ => unimplemented!("Points are two or three dimensional vectors (make-polygon)"),
-                });
-              }
+        Ok(EvaluatedValue::Native(extrude_flat(
+          "extrude",
+          args[1].span(),
+          &profile,
+          &mut Vec::new(),
+          &extrude,
+        )?))
+      }),
+    );
 
-              points
-            }
-            _ => unimplemented!("polygon needs a vector as points"),
+    env.functions.insert(
+      "rotate-extrude".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("rotate-extrude", span, args, 2, Some(3))?;
+
+        let angle = util::evaluate_number(env, &args[0])?;
+        if angle == 0.0 || angle.abs() > 360.0 {
+          return Err(EvalError::InvalidArgument(
+            format!(
+              "The angle needs to be between -360 and 360 (and not 0), got {}",
+              angle
+            ),
+            args[0].span(),
+          ));
+        }
+
+        let profile = evaluate_flat(env, &args[1])?;
+        let segments = match args.get(2) {
+          None => None,
+          Some(v) => Some(evaluate_count(env, v, 3)?),
+        };
+
+        let profile_span = args[1].span();
+        let revolve = |p: Polygon| {
+          // The profile's x is the distance from the axis it is revolved around
+          if let Some(q) = p.0.iter().find(|q| q.0 < 0.0) {
+            return Err(EvalError::InvalidArgument(
+              format!(
+                "The profile can't cross the axis it is revolved around, but has a point at x = {}",
+                q.0
+              ),
+              profile_span,
+            ));
+          }
+
+          let segments = segments
+            .unwrap_or_else(|| circle_segments(p.0.iter().map(|q| q.0).fold(0.0, f64::max)));
+          Ok(NativeValue::RotateExtrude(p, angle, segments))
+        };
+
+        Ok(EvaluatedValue::Native(extrude_flat(
+          "rotate-extrude",
+          profile_span,
+          &profile,
+          &mut Vec::new(),
+          &revolve,
+        )?))
+      }),
+    );
+
+    env.functions.insert(
+      "cube".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("cube", span, args, 1, Some(2))?;
+
+        // A single number makes a cube, and a 3D-vector a box
+        let size_span = args[0].span();
+        let size = match evaluate_expression(&args[0], env)? {
+          n @ EvaluatedValue::Number(_) => {
+            let n = util::cast_number(&n, size_span)?;
+            (n, n, n)
+          }
+          EvaluatedValue::Vector(v) if v.len() == 3 => {
+            util::cast_vector3(&EvaluatedValue::Vector(v), size_span)?
+          }
+          got => {
+            return Err(EvalError::UnexpectedValue {
+              expected: "a size (number or 3D-vector)".to_owned(),
+              got: Box::new(got),
+              span: size_span,
+            })
           }
         };
+        if size.0 <= 0.0 || size.1 <= 0.0 || size.2 <= 0.0 {
+          return Err(EvalError::InvalidArgument(
+            format!(
+              "The size needs to be positive, got ({} {} {})",
+              size.0, size.1, size.2
+            ),
+            size_span,
+          ));
+        }
 
+        let center = match args.get(1) {
+          None => false,
+          Some(v) => util::cast_condition(&evaluate_expression(v, env)?, v.span())?,
+        };
 
-        Ok(EvaluatedValue::Native(NativeValue::Polygon(Polygon (points, order, cur_pos))))
+        Ok(EvaluatedValue::Native(NativeValue::Cube(size, center)))
       }),
     );
 
     env.functions.insert(
-      "extrude".to_owned(),
-      EvalFunction::NativeFunction(|env, args| {
-        if args.len() != 2 {
-          unimplemented!("extrude: Please, pass a height and a polygon. No more for now.");
+      "sphere".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("sphere", span, args, 1, Some(2))?;
+
+        let r = evaluate_positive(env, &args[0], "radius")?;
+        let segments = match args.get(1) {
+          None => circle_segments(r),
+          Some(v) => evaluate_count(env, v, 3)?,
+        };
+
+        Ok(EvaluatedValue::Native(NativeValue::Sphere(r, segments)))
+      }),
+    );
+
+    env.functions.insert(
+      "cylinder".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("cylinder", span, args, 2, Some(3))?;
+
+        let h = evaluate_positive(env, &args[0], "height")?;
+        let r = evaluate_positive(env, &args[1], "radius")?;
+        let segments = match args.get(2) {
+          None => circle_segments(r),
+          Some(v) => evaluate_count(env, v, 3)?,
+        };
+
+        Ok(EvaluatedValue::Native(NativeValue::Cylinder(
+          h, r, r, segments,
+        )))
+      }),
+    );
+
+    env.functions.insert(
+      "cone".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("cone", span, args, 3, Some(4))?;
+
+        let h = evaluate_positive(env, &args[0], "height")?;
+        let r1 = util::evaluate_number(env, &args[1])?;
+        let r2 = util::evaluate_number(env, &args[2])?;
+        if r1 < 0.0 || r2 < 0.0 || r1 + r2 == 0.0 {
+          return Err(EvalError::InvalidArgument(
+            format!(
+              "A cone needs radii of at least zero, one of which is positive, got {} and {}",
+              r1, r2
+            ),
+            span,
+          ));
         }
+        let segments = match args.get(3) {
+          None => circle_segments(r1.max(r2)),
+          Some(v) => evaluate_count(env, v, 3)?,
+        };
 
-        match args.get(0) {
-          None => unimplemented!(),
-          Some(v) => match evaluate_expression(v, env)? {
-            EvaluatedValue::Number(h) => match args.get(1) {
-              None => unreachable!(),
-              Some(v) => match evaluate_expression(v, env)? {
-                EvaluatedValue::Native(nv) => match nv {
-                  NativeValue::Polygon(p) => Ok(EvaluatedValue::Native(NativeValue::Extrude(p, h))),
-                  NativeValue::Extrude(_, _) => {
-                    unimplemented!("Can not extrude a NativeValue(extrude)")
-                  }
-                },
-                _ => unimplemented!("extrude: Expected a native polygon"),
-              },
-            },
-            _ => unimplemented!("extrude: Expected a number (height)"),
-          },
+        Ok(EvaluatedValue::Native(NativeValue::Cylinder(
+          h, r1, r2, segments,
+        )))
+      }),
+    );
+
+    env.functions.insert(
+      "translate".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("translate", span, args, 2, None)?;
+
+        let offset = util::cast_vector3(&evaluate_expression(&args[0], env)?, args[0].span())?;
+
+        evaluate_transform(env, Transform::Translate(offset), &args[1..])
+      }),
+    );
+
+    env.functions.insert(
+      "current-position".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("current-position", span, args, 0, Some(0))?;
+
+        let (x, y, z) = env
+          .transforms
+          .iter()
+          .rev()
+          .fold((0.0, 0.0, 0.0), |p, t| t.apply(p));
+
+        Ok(EvaluatedValue::Vector(vec![
+          EvaluatedValue::Number(x),
+          EvaluatedValue::Number(y),
+          EvaluatedValue::Number(z),
+        ]))
+      }),
+    );
+
+    env.functions.insert(
+      "rotate".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("rotate", span, args, 2, None)?;
+
+        let angles = match evaluate_expression(&args[0], env)? {
+          // A single angle rotates around the z-axis, like in OpenSCAD
+          EvaluatedValue::Number(a) => (0.0, 0.0, a),
+          v => util::cast_vector3(&v, args[0].span())?,
+        };
+        if !(angles.0.is_finite() && angles.1.is_finite() && angles.2.is_finite()) {
+          return Err(EvalError::InvalidArgument(
+            format!(
+              "The angles need to be finite, got ({} {} {})",
+              angles.0, angles.1, angles.2
+            ),
+            args[0].span(),
+          ));
+        }
+
+        evaluate_transform(env, Transform::Rotate(angles), &args[1..])
+      }),
+    );
+
+    env.functions.insert(
+      "scale".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("scale", span, args, 2, None)?;
+
+        let factors = match evaluate_expression(&args[0], env)? {
+          // A single factor scales uniformly
+          EvaluatedValue::Number(f) => (f, f, f),
+          v => util::cast_vector3(&v, args[0].span())?,
+        };
+        if !(factors.0.is_finite() && factors.1.is_finite() && factors.2.is_finite()) {
+          return Err(EvalError::InvalidArgument(
+            format!(
+              "The factors need to be finite, got ({} {} {})",
+              factors.0, factors.1, factors.2
+            ),
+            args[0].span(),
+          ));
         }
+        // Nothing could be undone from scaling by zero, which flattens values completely
+        if factors.0 == 0.0 || factors.1 == 0.0 || factors.2 == 0.0 {
+          return Err(EvalError::InvalidArgument(
+            format!(
+              "Can't scale by zero, got ({} {} {})",
+              factors.0, factors.1, factors.2
+            ),
+            args[0].span(),
+          ));
+        }
+
+        evaluate_transform(env, Transform::Scale(factors), &args[1..])
+      }),
+    );
+
+    env.functions.insert(
+      "union".to_owned(),
+      EvalFunction::NativeFunction(|env, _span, args| {
+        let children = util::evaluate_natives(env, args)?;
+        Ok(EvaluatedValue::Native(NativeValue::Union(children)))
+      }),
+    );
+
+    env.functions.insert(
+      "difference".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("difference", span, args, 1, None)?;
+
+        let children = util::evaluate_natives(env, args)?;
+        if children.is_empty() {
+          return Err(EvalError::InvalidArgument(
+            "difference needs a value to subtract from".to_owned(),
+            span,
+          ));
+        }
+
+        Ok(EvaluatedValue::Native(NativeValue::Difference(children)))
+      }),
+    );
+
+    env.functions.insert(
+      "intersection".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("intersection", span, args, 1, None)?;
+
+        let children = util::evaluate_natives(env, args)?;
+        if children.is_empty() {
+          return Err(EvalError::InvalidArgument(
+            "intersection needs values to intersect".to_owned(),
+            span,
+          ));
+        }
+
+        Ok(EvaluatedValue::Native(NativeValue::Intersection(children)))
+      }),
+    );
+
+    env.functions.insert(
+      "hull".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("hull", span, args, 1, None)?;
+
+        let children = util::evaluate_natives(env, args)?;
+        expect_same_dimensions("hull", span, &children)?;
+
+        Ok(EvaluatedValue::Native(NativeValue::Hull(children)))
+      }),
+    );
+
+    env.functions.insert(
+      "minkowski".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("minkowski", span, args, 1, None)?;
+
+        let children = util::evaluate_natives(env, args)?;
+        expect_same_dimensions("minkowski", span, &children)?;
+
+        Ok(EvaluatedValue::Native(NativeValue::Minkowski(children)))
+      }),
+    );
+
+    env.functions.insert(
+      "shell".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("shell", span, args, 2, None)?;
+
+        let thickness = evaluate_count(env, &args[0], 1)?;
+        let children = util::evaluate_natives(env, &args[1..])?;
+        Ok(EvaluatedValue::Native(NativeValue::Shell(
+          thickness, children,
+        )))
+      }),
+    );
+
+    env.functions.insert(
+      "material".to_owned(),
+      EvalFunction::NativeFunction(|env, span, args| {
+        util::expect_args("material", span, args, 2, None)?;
+
+        // The name is used as is, rather than evaluated
+        let name = match &args[0] {
+          parse::Value::Identifier(name, _) => name.clone(),
+          a => {
+            return Err(EvalError::InvalidArgument(
+              "material needs the name of a material, like oak_planks".to_owned(),
+              a.span(),
+            ))
+          }
+        };
+
+        let children = util::evaluate_natives(env, &args[1..])?;
+        Ok(EvaluatedValue::Native(NativeValue::Material(
+          name, children,
+        )))
       }),
     );
 
@@ -383,6 +1036,8 @@ impl EvalEnvironment {
     self.functions.insert(name, function);
   }
 
+  /// Looks up a variable, from the innermost scope (like a `let`) out to the variables captured by
+  /// the function that is being called, and then the global variables
   pub fn get_variable(&self, name: &str) -> Option<&EvaluatedValue> {
     for c in self.locals.iter().rev() {
       match c.get(name) {
@@ -391,18 +1046,50 @@ impl EvalEnvironment {
       }
     }
 
-    self.variables.get(name)
+    self.captured.get(name).or_else(|| self.variables.get(name))
+  }
+
+  /// Checks if a variable is one that the function being called captured, rather than one of its
+  /// own or a global variable
+  fn is_captured(&self, name: &str) -> bool {
+    self.captured.contains_key(name) && !self.locals.iter().any(|c| c.contains_key(name))
+  }
+
+  /// Changes the innermost variable with the name, or sets a global variable if there is none
+  pub fn assign_variable(&mut self, name: String, value: EvaluatedValue) {
+    match self.locals.iter_mut().rev().find(|c| c.contains_key(&name)) {
+      None => self.set_variable(name, value),
+      Some(c) => {
+        c.insert(name, value);
+      }
+    }
+  }
+
+  /// All of the local variables that can be seen, for functions to capture
+  fn visible_locals(&self) -> HashMap<String, EvaluatedValue> {
+    let mut out = self.captured.clone();
+    for c in self.locals.iter() {
+      out.extend(c.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+    out
   }
 
   pub fn set_local(&mut self, name: String, value: EvaluatedValue) {
     match self.locals.last_mut() {
-      None => todo!(),
+      None => unreachable!("locals are only set within a function or let"),
       Some(v) => {
         v.insert(name, value);
       }
     }
   }
 
+  pub fn push_transform(&mut self, transform: Transform) {
+    self.transforms.push(transform);
+  }
+  pub fn pop_transform(&mut self) {
+    self.transforms.pop();
+  }
+
   pub fn push_defs(&mut self) {
     self.locals.push(HashMap::new());
   }
@@ -418,21 +1105,446 @@ pub enum EvaluatedValue {
   None,
   True,
   Native(NativeValue),
+  Function(Lambda),
+}
+
+impl EvaluatedValue {
+  /// A short description of the value, for error messages
+  pub fn describe(&self) -> String {
+    match self {
+      EvaluatedValue::Number(n) => format!("the number {}", n),
+      EvaluatedValue::Vector(v) => format!("a {}D-vector", v.len()),
+      EvaluatedValue::None => "nil".to_owned(),
+      EvaluatedValue::True => "t".to_owned(),
+      EvaluatedValue::Native(n) => format!("a native value ({})", n.name()),
+      EvaluatedValue::Function(_) => "a function".to_owned(),
+    }
+  }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum NativeValue {
   Polygon(Polygon),
-  Extrude(Polygon, f64),
+  Extrude(Polygon, Extrusion),
+  /// A polygon (in the xz-plane) revolved around the z-axis by an angle in degrees, starting at
+  /// the x-axis. The number of segments is for a whole revolution, like OpenSCAD's `$fn`.
+  RotateExtrude(Polygon, f64, usize),
+  /// The size, and if it is centred (otherwise its lowest corner is at the origin)
+  Cube((f64, f64, f64), bool),
+  /// A sphere around the origin: its radius, and the number of segments around it (like
+  /// OpenSCAD's `$fn`)
+  Sphere(f64, usize),
+  /// A cylinder standing on the origin, which is a cone when the radii differ: its height, the
+  /// radius at the bottom and at the top, and the number of segments around it
+  Cylinder(f64, f64, f64, usize),
+  Transform(Transform, Vec<NativeValue>),
+  Union(Vec<NativeValue>),
+  /// The first value with the rest of the values subtracted from it
+  Difference(Vec<NativeValue>),
+  Intersection(Vec<NativeValue>),
+  /// The smallest convex value containing all of the values
+  Hull(Vec<NativeValue>),
+  /// The values added together, as if every point of one was moved along every point of the other
+  /// (like rounding the corners of a cube by adding a sphere to it)
+  Minkowski(Vec<NativeValue>),
+  /// Only the outermost blocks of the values, this many blocks thick, when built in Minecraft.
+  /// Other backends draw the values solid.
+  Shell(usize, Vec<NativeValue>),
+  /// Values made of a material, like a Minecraft block (`oak_stairs[facing=east]`). Materials
+  /// set further in take precedence.
+  Material(String, Vec<NativeValue>),
 }
 
+impl NativeValue {
+  pub fn name(&self) -> &'static str {
+    match self {
+      NativeValue::Polygon(_) => "polygon",
+      NativeValue::Extrude(_, _) => "extrude",
+      NativeValue::RotateExtrude(_, _, _) => "rotate-extrude",
+      NativeValue::Cube(_, _) => "cube",
+      NativeValue::Sphere(_, _) => "sphere",
+      NativeValue::Cylinder(_, _, _, _) => "cylinder",
+      NativeValue::Transform(_, _) => "transform",
+      NativeValue::Union(_) => "union",
+      NativeValue::Difference(_) => "difference",
+      NativeValue::Intersection(_) => "intersection",
+      NativeValue::Hull(_) => "hull",
+      NativeValue::Minkowski(_) => "minkowski",
+      NativeValue::Shell(_, _) => "shell",
+      NativeValue::Material(_, _) => "material",
+    }
+  }
+
+  /// If it is a 2D value, like a polygon. Like in the mesh backends, values mixing 2D and 3D values
+  /// count as 2D.
+  pub fn is_flat(&self) -> bool {
+    match self {
+      NativeValue::Polygon(_) => true,
+      NativeValue::Extrude(_, _)
+      | NativeValue::RotateExtrude(_, _, _)
+      | NativeValue::Cube(_, _)
+      | NativeValue::Sphere(_, _)
+      | NativeValue::Cylinder(_, _, _, _) => false,
+      NativeValue::Transform(_, children)
+      | NativeValue::Union(children)
+      | NativeValue::Hull(children)
+      | NativeValue::Minkowski(children)
+      | NativeValue::Shell(_, children)
+      | NativeValue::Material(_, children) => children.iter().any(NativeValue::is_flat),
+      NativeValue::Difference(children) | NativeValue::Intersection(children) => {
+        children.first().map(NativeValue::is_flat).unwrap_or(false)
+      }
+    }
+  }
+}
+
+/// The points of a polygon and, like OpenSCAD's `paths`, the rings (outlines and holes) they
+/// make up, as indices of the points. Without paths, all of the points make up a single ring.
+/// Areas inside of an odd number of rings are filled.
+///
+/// Polygons made by primitives like `circle` also keep the shape they were made from, so backends
+/// can draw it natively.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Polygon(
   pub Vec<(f64, f64)>,
-  pub Option<Vec<usize>>,
-  pub (f64, f64, f64),
+  pub Option<Vec<Vec<usize>>>,
+  pub Option<Shape>,
 );
 
+/// How a polygon is extruded, like the parameters of OpenSCAD's `linear_extrude`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Extrusion {
+  pub height: f64,
+  /// The angle (in degrees) the top is turned by, clockwise like in OpenSCAD
+  pub twist: f64,
+  /// How much the top is scaled along x and y
+  pub top_scale: (f64, f64),
+  /// How many parts the height is split into, which is what makes twists smooth
+  pub slices: usize,
+  /// If it goes from `-height / 2` to `height / 2`, rather than from 0 to `height`
+  pub center: bool,
+}
+
+impl Extrusion {
+  /// If it isn't twisted or scaled, so the polygon is the same at every height
+  pub fn is_straight(&self) -> bool {
+    self.twist == 0.0 && self.top_scale == (1.0, 1.0)
+  }
+
+  pub fn bottom(&self) -> f64 {
+    if self.center {
+      -self.height / 2.0
+    } else {
+      0.0
+    }
+  }
+
+  fn scale_at(&self, t: f64) -> (f64, f64) {
+    (
+      1.0 + (self.top_scale.0 - 1.0) * t,
+      1.0 + (self.top_scale.1 - 1.0) * t,
+    )
+  }
+
+  /// Where a point of the polygon ends up at a height, as a fraction `t` of the extrusion's height
+  pub fn apply(&self, p: (f64, f64), t: f64) -> (f64, f64) {
+    let (sx, sy) = self.scale_at(t);
+    let (x, y) = (p.0 * sx, p.1 * sy);
+    let (sin, cos) = (-self.twist * t).to_radians().sin_cos();
+    (x * cos - y * sin, x * sin + y * cos)
+  }
+
+  /// Where a point at a height (as a fraction `t` of the extrusion's height) was on the polygon,
+  /// or `None` if the polygon is scaled to nothing there
+  pub fn apply_inverse(&self, p: (f64, f64), t: f64) -> Option<(f64, f64)> {
+    let (sin, cos) = (self.twist * t).to_radians().sin_cos();
+    let (x, y) = (p.0 * cos - p.1 * sin, p.0 * sin + p.1 * cos);
+    let (sx, sy) = self.scale_at(t);
+    if sx == 0.0 || sy == 0.0 {
+      return None;
+    }
+
+    Some((x / sx, y / sy))
+  }
+}
+
+/// A 2D primitive, centred on the origin unless noted otherwise
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+  /// The radius, and the number of segments the points use (like OpenSCAD's `$fn`)
+  Circle(f64, usize),
+  /// The size, and if it is centred (otherwise its lowest corner is at the origin)
+  Square((f64, f64), bool),
+  /// A regular polygon with this many sides, and the radius of its corners
+  Ngon(usize, f64),
+  /// A polygon grown by an amount (or shrunk, if it is negative)
+  Offset(Box<Polygon>, f64, Join),
+}
+
+/// How the corners of an offset polygon are joined, like in OpenSCAD
+#[derive(Clone, Debug, PartialEq)]
+pub enum Join {
+  /// Rounded, with as many segments as a whole circle would have
+  Round(usize),
+  /// Sharp, with the edges extended until they meet
+  Miter,
+  /// Cut off straight
+  Chamfer,
+}
+
+impl Polygon {
+  pub fn rings(&self) -> Vec<Vec<(f64, f64)>> {
+    match &self.1 {
+      None => vec![self.0.clone()],
+      Some(paths) => paths
+        .iter()
+        .map(|path| path.iter().map(|&i| self.0[i]).collect())
+        .collect(),
+    }
+  }
+}
+
+/// Makes sure the values are either all 2D or all 3D, which operations like `hull` need
+fn expect_same_dimensions(
+  function: &str,
+  span: Span,
+  children: &[NativeValue],
+) -> Result<(), EvalError> {
+  let flat = children.iter().filter(|c| c.is_flat()).count();
+  if flat != 0 && flat != children.len() {
+    return Err(EvalError::InvalidArgument(
+      format!(
+        "{} can't combine 2D values (like polygons) with 3D values (like extrusions)",
+        function
+      ),
+      span,
+    ));
+  }
+
+  Ok(())
+}
+
+/// Evaluates a value that has to be flat, like the profile of an extrusion
+fn evaluate_flat(env: &mut EvalEnvironment, arg: &parse::Value) -> Result<NativeValue, EvalError> {
+  match evaluate_expression(arg, env)? {
+    EvaluatedValue::Native(n) if n.is_flat() => Ok(n),
+    got => Err(EvalError::UnexpectedValue {
+      expected: "a flat value (like a polygon)".to_owned(),
+      got: Box::new(got),
+      span: arg.span(),
+    }),
+  }
+}
+
+/// Turns a flat value into a solid by making one (with `solid`) from every polygon in it. Booleans
+/// and materials are kept around the solids, while transforms are applied to the polygons' points,
+/// since the polygons have to stay in the plane they are extruded from.
+fn extrude_flat(
+  function: &str,
+  span: Span,
+  value: &NativeValue,
+  transforms: &mut Vec<Transform>,
+  solid: &dyn Fn(Polygon) -> Result<NativeValue, EvalError>,
+) -> Result<NativeValue, EvalError> {
+  let children = |children: &[NativeValue], transforms: &mut Vec<Transform>| {
+    children
+      .iter()
+      .map(|c| extrude_flat(function, span, c, transforms, solid))
+      .collect::<Result<Vec<NativeValue>, EvalError>>()
+  };
+
+  Ok(match value {
+    NativeValue::Polygon(p) if transforms.is_empty() => solid(p.clone())?,
+    NativeValue::Polygon(p) => {
+      let mut points = Vec::new();
+      for &(x, y) in &p.0 {
+        let q = transforms.iter().rev().fold((x, y, 0.0), |q, t| t.apply(q));
+        if q.2.abs() > 1e-9 {
+          return Err(EvalError::InvalidArgument(
+            format!(
+              "{} can only extrude polygons in the xy-plane, but a point was moved to z = {}",
+              function, q.2
+            ),
+            span,
+          ));
+        }
+        points.push((q.0, q.1));
+      }
+      // The shape isn't where the polygon is anymore
+      solid(Polygon(points, p.1.clone(), None))?
+    }
+    NativeValue::Transform(t, c) => {
+      transforms.push(t.clone());
+      let mut c = children(c, transforms)?;
+      transforms.pop();
+      if c.len() == 1 {
+        c.remove(0)
+      } else {
+        NativeValue::Union(c)
+      }
+    }
+    NativeValue::Union(c) => NativeValue::Union(children(c, transforms)?),
+    NativeValue::Difference(c) => NativeValue::Difference(children(c, transforms)?),
+    NativeValue::Intersection(c) => NativeValue::Intersection(children(c, transforms)?),
+    NativeValue::Material(name, c) => NativeValue::Material(name.clone(), children(c, transforms)?),
+    v => {
+      return Err(EvalError::InvalidArgument(
+        format!(
+          "{} can only extrude polygons, and transforms, unions, differences, intersections and \
+           materials of them, got a {}",
+          function,
+          v.name()
+        ),
+        span,
+      ))
+    }
+  })
+}
+
+/// The number of segments OpenSCAD uses for a circle by default (with `$fa = 12` and `$fs = 2`)
+fn circle_segments(r: f64) -> usize {
+  (360.0 / 12.0_f64)
+    .min(r * std::f64::consts::PI)
+    .max(5.0)
+    .ceil() as usize
+}
+
+/// Points evenly spread around a circle, starting on the x-axis like OpenSCAD's
+pub fn circle_points(segments: usize, r: f64) -> Vec<(f64, f64)> {
+  (0..segments)
+    .map(|i| {
+      let angle = 2.0 * std::f64::consts::PI * i as f64 / segments as f64;
+      (r * angle.cos(), r * angle.sin())
+    })
+    .collect()
+}
+
+fn rectangle(size: (f64, f64), center: bool) -> NativeValue {
+  let (x0, y0) = if center {
+    (-size.0 / 2.0, -size.1 / 2.0)
+  } else {
+    (0.0, 0.0)
+  };
+  let (x1, y1) = (x0 + size.0, y0 + size.1);
+
+  NativeValue::Polygon(Polygon(
+    vec![(x0, y0), (x1, y0), (x1, y1), (x0, y1)],
+    None,
+    Some(Shape::Square(size, center)),
+  ))
+}
+
+fn evaluate_positive(
+  env: &mut EvalEnvironment,
+  arg: &parse::Value,
+  what: &str,
+) -> Result<f64, EvalError> {
+  let v = util::evaluate_number(env, arg)?;
+  if !v.is_finite() || v <= 0.0 {
+    return Err(EvalError::InvalidArgument(
+      format!("The {} needs to be positive, got {}", what, v),
+      arg.span(),
+    ));
+  }
+
+  Ok(v)
+}
+
+/// The most segments, slices or layers a count can ask for. Even this many is far more than any
+/// backend needs, and larger counts would only run out of memory.
+const MAX_COUNT: usize = 10_000;
+
+/// Evaluates a count (like the number of segments of a circle), which has to be a whole number
+/// from `min` to `MAX_COUNT`
+fn evaluate_count(
+  env: &mut EvalEnvironment,
+  arg: &parse::Value,
+  min: usize,
+) -> Result<usize, EvalError> {
+  let n = util::evaluate_number(env, arg)?;
+  if n < min as f64 || n > MAX_COUNT as f64 || n.fract() != 0.0 {
+    return Err(EvalError::InvalidArgument(
+      format!(
+        "Expected a whole number from {} to {}, got {}",
+        min, MAX_COUNT, n
+      ),
+      arg.span(),
+    ));
+  }
+
+  Ok(n as usize)
+}
+
+/// Evaluates the paths of a polygon: a vector of paths, or a single path, which are vectors of
+/// point indices
+fn evaluate_paths(
+  env: &mut EvalEnvironment,
+  arg: &parse::Value,
+  point_count: usize,
+) -> Result<Vec<Vec<usize>>, EvalError> {
+  let span = arg.span();
+  let values = match evaluate_expression(arg, env)? {
+    EvaluatedValue::Vector(values) => values,
+    got => {
+      return Err(EvalError::UnexpectedValue {
+        expected: "a vector of paths (the order of the points)".to_owned(),
+        got: Box::new(got),
+        span,
+      })
+    }
+  };
+
+  let paths = if values
+    .iter()
+    .all(|v| matches!(v, EvaluatedValue::Number(_)))
+  {
+    vec![values]
+  } else {
+    let mut paths = Vec::new();
+    for v in values {
+      match v {
+        EvaluatedValue::Vector(path) => paths.push(path),
+        got => {
+          return Err(EvalError::UnexpectedValue {
+            expected: "a path (vector of point indices)".to_owned(),
+            got: Box::new(got),
+            span,
+          })
+        }
+      }
+    }
+    paths
+  };
+
+  let mut out = Vec::new();
+  for path in paths {
+    if path.len() < 3 {
+      return Err(EvalError::InvalidArgument(
+        format!("A path needs at least three points, got {}", path.len()),
+        span,
+      ));
+    }
+
+    let mut indices = Vec::new();
+    for i in path {
+      let n = util::cast_number(&i, span)?;
+      if n < 0.0 || n.fract() != 0.0 || n >= point_count as f64 {
+        return Err(EvalError::InvalidArgument(
+          format!(
+            "{} is not the index of a point (the polygon has {} points)",
+            n, point_count
+          ),
+          span,
+        ));
+      }
+      indices.push(n as usize);
+    }
+    out.push(indices);
+  }
+
+  Ok(out)
+}
+
 pub fn evaluate(parsed: Vec<parse::Value>) -> Result<Vec<EvaluatedValue>, EvalError> {
   let mut env = EvalEnvironment::default();
 
@@ -450,80 +1562,62 @@ fn evaluate_expression(
 ) -> Result<EvaluatedValue, EvalError> {
   Ok(match expression {
     parse::Value::SExpression(sexpr) => {
-      if sexpr.is_first(&parse::Value::Identifier("set".to_owned())) == Some(true) {
+      if sexpr.is_first_identifier("set") {
         let body = sexpr.body();
-        if body.len() != 3 {
-          unimplemented!();
-        }
-
-        match body.get(1) {
-          None => unreachable!(),
-          Some(parse::Value::Identifier(name)) => {
-            let value = match body.get(2) {
-              None => unreachable!(),
-              Some(v) => evaluate_expression(v, env)?,
-            };
+        util::expect_args("set", sexpr.span(), &body[1..], 2, Some(2))?;
+
+        match &body[1] {
+          parse::Value::Identifier(name, span) => {
+            if env.is_captured(name) {
+              return Err(EvalError::InvalidArgument(
+                format!(
+                  "Can't set {}, since the function only has a copy of it from where it was \
+                   defined",
+                  name
+                ),
+                *span,
+              ));
+            }
 
-            env.set_variable(name.clone(), value);
+            let value = evaluate_expression(&body[2], env)?;
+            env.assign_variable(name.clone(), value);
           }
-          Some(e) => return Err(EvalError::UnexpectedExpression(e.to_owned())),
+          e => return Err(EvalError::UnexpectedExpression(e.to_owned())),
         }
 
         EvaluatedValue::None
-      } else if sexpr.is_first(&parse::Value::Identifier("fn".to_owned())) == Some(true) {
+      } else if sexpr.is_first_identifier("fn") {
         let body = sexpr.body();
+        util::expect_args("fn", sexpr.span(), &body[1..], 2, None)?;
 
-        match body.get(1) {
-          None => unimplemented!(),
-          Some(parse::Value::Identifier(name)) => {
-            let args = match body.get(2) {
-              Some(parse::Value::SExpression(args_expr)) => {
-                let args = args_expr.body();
-                let mut out = Vec::new();
-
-                for a in args {
-                  match a {
-                    parse::Value::Identifier(name) => out.push((name.to_owned(), None)),
-                    parse::Value::SExpression(sexp) => {
-                      let body = sexp.body();
-                      if body.len() != 2 {
-                        unimplemented!();
-                      }
-
-                      match body.get(0) {
-                        Some(parse::Value::Identifier(name)) => match body.get(1) {
-                          None => unreachable!(),
-                          Some(v) => out.push((name.to_owned(), Some(v.clone()))),
-                        },
-                        None => unreachable!(),
-                        Some(_) => unimplemented!(),
-                      }
-                    }
-                    _ => unimplemented!(),
-                  }
-                }
+        let name = match &body[1] {
+          parse::Value::Identifier(name, _) => name,
+          e => return Err(EvalError::UnexpectedExpression(e.to_owned())),
+        };
 
-                out
-              }
-              None => unimplemented!(),
-              Some(_) => unimplemented!(),
-            };
+        let args = evaluate_arguments(&body[2])?;
 
-            let f_body: Vec<parse::Value> = (&body[3..]).iter().map(|x| x.clone()).collect();
+        let f_body: Vec<parse::Value> = body[3..].to_vec();
 
-            let f = LispFunction::new(args, f_body);
-            env.set_function(name.clone(), EvalFunction::LispFunction(f));
-          }
-          Some(_) => unimplemented!(),
-        }
+        let f = LispFunction::new(args, f_body, env.visible_locals());
+        env.set_function(name.clone(), EvalFunction::LispFunction(f));
 
         EvaluatedValue::None
+      } else if sexpr.is_first_identifier("lambda") {
+        let body = sexpr.body();
+        util::expect_args("lambda", sexpr.span(), &body[1..], 2, None)?;
+
+        let args = evaluate_arguments(&body[1])?;
+        let f = LispFunction::new(args, body[2..].to_vec(), env.visible_locals());
+        EvaluatedValue::Function(Lambda(f))
+      } else if sexpr.is_first_identifier("let") || sexpr.is_first_identifier("let*") {
+        evaluate_let(env, sexpr)?
       } else {
         match sexpr.first() {
-          // Empty SExpression
-          None => todo!("Empty sexpr, treat as array (or nil?)"),
+          // Empty SExpression, nil like in most lisps
+          None => EvaluatedValue::None,
           // SExpression where the first item is an identifier (may be a variable or function)
-          Some(parse::Value::Identifier(id)) => {
+          Some(parse::Value::Identifier(id, _)) => {
             match env.functions.get(id) {
               // SExpression where the first item is a function
               Some(f) => {
@@ -531,7 +1625,7 @@ fn evaluate_expression(
                 let f = f.clone();
 
                 let body = sexpr.body();
-                call_function(env, &f, &body[1..])?
+                call_function(env, id, &f, sexpr.span(), &body[1..])?
               }
               // SExpression where the first item is an identifier but not a function
               None => {
@@ -554,52 +1648,381 @@ fn evaluate_expression(
         }
       }
     }
-    parse::Value::Identifier(name) => match env.get_variable(name) {
-      None => {
-        panic!("Unknown variable: {:?}", name);
-      }
+    parse::Value::Identifier(name, span) => match env.get_variable(name) {
+      None => return Err(EvalError::UnknownVariable(name.to_owned(), *span)),
       Some(value) => value.clone(),
     },
-    parse::Value::Number(n) => EvaluatedValue::Number(*n),
+    parse::Value::Number(n, _) => EvaluatedValue::Number(*n),
   })
 }
 
+/// The arguments of a `fn` or `lambda`, like `(width (height 1))`, where height defaults to 1
+fn evaluate_arguments(
+  args: &parse::Value,
+) -> Result<Vec<(String, Option<parse::Value>)>, EvalError> {
+  let args_expr = match args {
+    parse::Value::SExpression(args_expr) => args_expr,
+    e => return Err(EvalError::UnexpectedExpression(e.to_owned())),
+  };
+
+  let mut out = Vec::new();
+  for a in args_expr.body() {
+    match a {
+      parse::Value::Identifier(name, _) => out.push((name.to_owned(), None)),
+      // An argument with a default value, like `(height 1)`
+      parse::Value::SExpression(sexp) => match sexp.body().as_slice() {
+        [parse::Value::Identifier(name, _), default] => {
+          out.push((name.to_owned(), Some(default.clone())))
+        }
+        _ => return Err(EvalError::UnexpectedExpression(a.to_owned())),
+      },
+      _ => return Err(EvalError::UnexpectedExpression(a.to_owned())),
+    }
+  }
+
+  Ok(out)
+}
+
+fn evaluate_lambda(env: &mut EvalEnvironment, arg: &parse::Value) -> Result<Lambda, EvalError> {
+  match evaluate_expression(arg, env)? {
+    EvaluatedValue::Function(f) => Ok(f),
+    v => Err(EvalError::UnexpectedValue {
+      expected: "a function (made by lambda)".to_owned(),
+      got: Box::new(v),
+      span: arg.span(),
+    }),
+  }
+}
+
+/// Evaluates the body of a transform (like translate) with the transform pushed, and wraps the
+/// native values it produces in a `NativeValue::Transform`.
+fn evaluate_transform(
+  env: &mut EvalEnvironment,
+  transform: Transform,
+  body: &[parse::Value],
+) -> Result<EvaluatedValue, EvalError> {
+  env.push_transform(transform.clone());
+  let children = util::evaluate_natives(env, body);
+  env.pop_transform();
+
+  Ok(EvaluatedValue::Native(NativeValue::Transform(
+    transform, children?,
+  )))
+}
+
 fn call_function(
   env: &mut EvalEnvironment,
+  name: &str,
   f: &EvalFunction,
+  span: Span,
   args: &[parse::Value],
 ) -> Result<EvaluatedValue, EvalError> {
+  match f {
+    EvalFunction::LispFunction(lf) => {
+      let mut values = Vec::new();
+      for a in args {
+        values.push(evaluate_expression(a, env)?);
+      }
+      lf.call(env, name, span, values)
+    }
+    EvalFunction::NativeFunction(nf) => nf(env, span, args),
+  }
+}
+
+/// Evaluates a `let`, like `(let ((x 1) (y 2)) body...)`, with the variables only set within the
+/// body. `let` evaluates every value before setting any of the variables, while `let*` sets them
+/// one at a time, so a value can use the variables before it.
+fn evaluate_let(
+  env: &mut EvalEnvironment,
+  sexpr: &parse::SExpression,
+) -> Result<EvaluatedValue, EvalError> {
+  let body = sexpr.body();
+  let sequential = sexpr.is_first_identifier("let*");
+  let form = if sequential { "let*" } else { "let" };
+  util::expect_args(form, sexpr.span(), &body[1..], 1, None)?;
+
+  let bindings = match &body[1] {
+    parse::Value::SExpression(bindings) => bindings.body(),
+    e => return Err(EvalError::UnexpectedExpression(e.to_owned())),
+  };
+
   env.push_defs();
+  let rv = evaluate_let_body(env, bindings, sequential, &body[2..]);
+  env.pop_defs();
 
-  let rv = match f {
-    EvalFunction::LispFunction(lf) => {
-      for (i, arg) in lf.arguments.iter().enumerate() {
-        let value = match args.get(i) {
-          None => match &arg.1 {
-            None => {
-              println!("Did not pass a value to an argument with no default value (Kinda not supposed to happen?) @ {:?}", f);
-              EvaluatedValue::None
-            }
-            Some(v) => evaluate_expression(&v, env)?,
-          },
-          Some(v) => evaluate_expression(v, env)?,
-        };
-        env.set_local(arg.0.to_owned(), value)
+  rv
+}
+
+fn evaluate_let_body(
+  env: &mut EvalEnvironment,
+  bindings: &[parse::Value],
+  sequential: bool,
+  body: &[parse::Value],
+) -> Result<EvaluatedValue, EvalError> {
+  let mut values = Vec::new();
+  for b in bindings {
+    // A variable without a value, like `x` instead of `(x 1)`, is nil
+    let (name, value) = match b {
+      parse::Value::Identifier(name, _) => (name, EvaluatedValue::None),
+      parse::Value::SExpression(sexp) => match sexp.body().as_slice() {
+        [parse::Value::Identifier(name, _), value] => (name, evaluate_expression(value, env)?),
+        _ => return Err(EvalError::UnexpectedExpression(b.to_owned())),
+      },
+      _ => return Err(EvalError::UnexpectedExpression(b.to_owned())),
+    };
+
+    if sequential {
+      env.set_local(name.clone(), value);
+    } else {
+      values.push((name.clone(), value));
+    }
+  }
+  for (name, value) in values {
+    env.set_local(name, value);
+  }
+
+  let mut out = EvaluatedValue::None;
+  for expr in body {
+    out = evaluate_expression(expr, env)?;
+  }
+
+  Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn run(src: &str) -> Result<Vec<EvaluatedValue>, EvalError> {
+    evaluate(parse::parse(crate::lex::lex(src)).unwrap())
+  }
+
+  fn native(src: &str) -> NativeValue {
+    match run(src).unwrap().pop() {
+      Some(EvaluatedValue::Native(n)) => n,
+      v => panic!("expected a native value, got {:?}", v),
+    }
+  }
+
+  #[test]
+  fn functions_do_not_see_the_variables_of_their_caller() {
+    match run("(fn f () x) (fn g () (let ((x 5)) (f))) (g)") {
+      Err(EvalError::UnknownVariable(name, _)) => assert_eq!(name, "x"),
+      r => panic!("expected an unknown variable, got {:?}", r),
+    }
+
+    // But they do see the ones where they were defined
+    let src = "(let ((x 5)) (fn f () x)) (fn g () (let ((x 6)) (f))) (g)";
+    assert_eq!(run(src).unwrap()[2], EvaluatedValue::Number(5.0));
+  }
+
+  #[test]
+  fn let_binds_in_parallel_and_let_star_in_order() {
+    let src = "(let ((x 1)) (let ((x 2) (y x)) y))";
+    assert_eq!(run(src).unwrap(), [EvaluatedValue::Number(1.0)]);
+
+    let src = "(let ((x 1)) (let* ((x 2) (y x)) y))";
+    assert_eq!(run(src).unwrap(), [EvaluatedValue::Number(2.0)]);
+
+    match run("(let ((a 1) (b a)) b)") {
+      Err(EvalError::UnknownVariable(name, _)) => assert_eq!(name, "a"),
+      r => panic!("expected an unknown variable, got {:?}", r),
+    }
+    assert_eq!(
+      run("(let* ((a 1) (b (+ a 1))) b)").unwrap(),
+      [EvaluatedValue::Number(2.0)]
+    );
+
+    // The variables are gone after the body
+    assert!(run("(let ((a 1)) a) a").is_err());
+  }
+
+  #[test]
+  fn functions_can_not_set_captured_variables() {
+    let src = "(let ((c 0)) (fn inc () (set c (+ c 1)) c) (inc))";
+    match run(src) {
+      Err(EvalError::InvalidArgument(_, _)) => {}
+      r => panic!("expected an error, got {:?}", r),
+    }
+
+    // Their own variables, and global ones, can be set
+    let src = "(set g 1) (let ((c 0)) (fn f (c) (set c (+ c 1)) (set g c) c) (f 5)) g";
+    assert_eq!(
+      run(src).unwrap()[1..],
+      [EvaluatedValue::Number(6.0), EvaluatedValue::Number(6.0)]
+    );
+  }
+
+  #[test]
+  fn rotate_extrudes_translated_profiles() {
+    let square = |x: f64| {
+      Polygon(
+        vec![(x, 0.0), (x + 1.0, 0.0), (x + 1.0, 1.0), (x, 1.0)],
+        None,
+        None,
+      )
+    };
+    assert_eq!(
+      native("(rotate-extrude 360 (translate (3 0 0) (polygon ((0 0) (1 0) (1 1) (0 1)))) 8)"),
+      NativeValue::RotateExtrude(square(3.0), 360.0, 8)
+    );
+    assert_eq!(
+      native(
+        "(rotate-extrude 90 (material stone (polygon ((2 0) (3 0) (3 1) (2 1))) \
+           (translate (2 0 0) (polygon ((2 0) (3 0) (3 1) (2 1))))) 8)"
+      ),
+      NativeValue::Material(
+        "stone".to_owned(),
+        vec![
+          NativeValue::RotateExtrude(square(2.0), 90.0, 8),
+          NativeValue::RotateExtrude(square(4.0), 90.0, 8),
+        ]
+      )
+    );
+  }
+
+  #[test]
+  fn extrudes_flat_values() {
+    let extrusion = Extrusion {
+      height: 2.0,
+      twist: 0.0,
+      top_scale: (1.0, 1.0),
+      slices: 1,
+      center: false,
+    };
+    let square = Polygon(
+      vec![(-2.0, -2.0), (2.0, -2.0), (2.0, 2.0), (-2.0, 2.0)],
+      None,
+      Some(Shape::Square((4.0, 4.0), true)),
+    );
+    let circle = Polygon(circle_points(8, 1.0), None, Some(Shape::Circle(1.0, 8)));
+    assert_eq!(
+      native("(extrude 2 (difference (square 4 t) (circle 1 8)))"),
+      NativeValue::Difference(vec![
+        NativeValue::Extrude(square, extrusion.clone()),
+        NativeValue::Extrude(circle, extrusion.clone()),
+      ])
+    );
+
+    assert_eq!(
+      native("(extrude 2 (material stone (translate (1 2 0) (polygon ((0 0) (1 0) (0 1))))))"),
+      NativeValue::Material(
+        "stone".to_owned(),
+        vec![NativeValue::Extrude(
+          Polygon(vec![(1.0, 2.0), (2.0, 2.0), (1.0, 3.0)], None, None),
+          extrusion
+        )]
+      )
+    );
+  }
+
+  #[test]
+  fn nth_takes_indices_within_the_vector() {
+    assert_eq!(
+      run("(nth 1 (4 5 6))").unwrap(),
+      [EvaluatedValue::Number(5.0)]
+    );
+
+    for src in &["(nth -1 (4 5 6))", "(nth 0.5 (4 5 6))", "(nth 3 (4 5 6))"] {
+      match run(src) {
+        Err(EvalError::InvalidArgument(..)) => {}
+        r => panic!("{} should be an invalid argument, got {:?}", src, r),
+      }
+    }
+  }
+
+  #[test]
+  fn rejects_invalid_transforms() {
+    // Scaling by zero, and by or around numbers that overflowed
+    for src in &[
+      "(scale 0 (cube 1))",
+      "(scale (1 0 1) (cube 1))",
+      "(scale (+ 1e308 1e308) (cube 1))",
+      "(scale (1 (- -1e308 1e308) 1) (cube 1))",
+      "(rotate (+ 1e308 1e308) (cube 1))",
+      "(rotate (0 (+ 1e308 1e308) 0) (cube 1))",
+    ] {
+      match run(src) {
+        Err(EvalError::InvalidArgument(..)) => {}
+        r => panic!("{} should be an invalid argument, got {:?}", src, r),
+      }
+    }
+
+    // `nan` is a name, not a number
+    assert!(run("(scale nan (cube 1))").is_err());
+    assert!(run("(rotate nan (cube 1))").is_err());
+  }
+
+  #[test]
+  fn rejects_numbers_that_are_not_finite() {
+    for src in &[
+      "(cube 1e400)",
+      "(square (+ 1e308 1e308))",
+      "(circle 1e400)",
+      "(polygon ((0 0) (1e400 0) (1 1)))",
+      "(extrude 1 (circle 1) :top-scale -1e400)",
+    ] {
+      match run(src) {
+        Err(EvalError::InvalidArgument(..)) => {}
+        r => panic!("{} should be an invalid argument, got {:?}", src, r),
       }
+    }
 
-      let mut out = Vec::new();
-      for expr in &lf.body {
-        let res = evaluate_expression(expr, env)?;
-        out.push(res);
+    // Words Rust would read as numbers are just names
+    match run("(cube nan)") {
+      Err(EvalError::UnknownVariable(name, _)) => assert_eq!(name, "nan"),
+      r => panic!("expected an unknown variable, got {:?}", r),
+    }
+  }
+
+  #[test]
+  fn rejects_invalid_counts() {
+    for src in &[
+      "(circle 1 1e20)",
+      "(circle 1 2.5)",
+      "(circle 1 2)",
+      "(ngon 1e400 1)",
+      "(sphere 1 -4)",
+      "(extrude 1 (circle 1) :slices 10001)",
+      "(shell 0.5 (cube 1))",
+    ] {
+      match run(src) {
+        Err(EvalError::InvalidArgument(..)) => {}
+        r => panic!("{} should be an invalid argument, got {:?}", src, r),
       }
+    }
 
-      //TODO Aviod cloning
-      out.last().unwrap_or(&EvaluatedValue::None).to_owned()
+    assert!(run("(circle 1 10000)").is_ok());
+    // Slices for a twist are capped the same way
+    match native("(extrude 1 (circle 1) :twist 1e20)") {
+      NativeValue::Extrude(_, e) => assert_eq!(e.slices, MAX_COUNT),
+      v => panic!("expected an extrusion, got {:?}", v),
     }
-    EvalFunction::NativeFunction(nf) => nf(env, args)?,
-  };
+  }
 
-  env.pop_defs();
+  #[test]
+  fn rejects_invalid_extrusions() {
+    for src in &[
+      "(extrude -1 (circle 1))",
+      "(extrude 0 (circle 1))",
+      "(extrude 1 (translate (0 0 1) (circle 1)))",
+      "(extrude 1 (cube 1))",
+      "(extrude 1 (hull (circle 1) (translate (3 0 0) (circle 1))))",
+    ] {
+      assert!(run(src).is_err(), "{} should fail", src);
+    }
+  }
 
-  Ok(rv)
+  #[test]
+  fn rejects_profiles_that_can_not_be_revolved() {
+    // Across the axis, out of the xy-plane, and not flat
+    for src in &[
+      "(rotate-extrude 360 (translate (0.5 0 0) (circle 1)))",
+      "(rotate-extrude 360 (translate (3 0 1) (circle 1)))",
+      "(rotate-extrude 360 (rotate (90 0 0) (translate (3 1 0) (circle 1))))",
+      "(rotate-extrude 360 (cube 1))",
+    ] {
+      assert!(run(src).is_err(), "{} should fail", src);
+    }
+  }
 }
@@ -0,0 +1,356 @@
+// Offsetting polygons. Every edge is moved along its normal and the corners between them are
+// joined, which gives a raw outline that crosses itself wherever the offset swallowed part of the
+// polygon. The outline is split where it crosses itself, and the parts that bound the area it
+// winds around (counter-clockwise) are kept.
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use super::plane::{self, cross, length, sub, Point2};
+use super::{Join, Polygon};
+
+/// Grows a polygon by `delta`, or shrinks it if `delta` is negative. Returns the points and the
+/// rings they make up (as indices of the points), which may be none if nothing is left.
+pub fn offset(polygon: &Polygon, delta: f64, join: &Join) -> (Vec<Point2>, Vec<Vec<usize>>) {
+    let rings = plane::oriented_rings(polygon);
+
+    let raw: Vec<Vec<Point2>> = rings
+        .iter()
+        .map(|(r, _)| raw_offset(r, delta, join))
+        .collect();
+    let segments: Vec<(Point2, Point2)> = raw
+        .iter()
+        .flat_map(|ring| {
+            ring.iter()
+                .enumerate()
+                .map(move |(i, &a)| (a, ring[(i + 1) % ring.len()]))
+        })
+        .filter(|(a, b)| a != b)
+        .collect();
+
+    // How far off an edge to look for the area it bounds
+    let extent = segments
+        .iter()
+        .fold(1.0, |m: f64, (a, _)| m.max(a.0.abs()).max(a.1.abs()));
+    let eps = extent * 1e-9;
+
+    let bands = Bands::new(&segments);
+    let mut edges = Vec::new();
+    for (a, b) in split_segments(&segments) {
+        let d = sub(b, a);
+        let len = length(d);
+        if len <= eps {
+            continue;
+        }
+
+        let mid = ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+        let left = (-d.1 / len * eps * 10.0, d.0 / len * eps * 10.0);
+        let inside_left = bands.winding((mid.0 + left.0, mid.1 + left.1)) > 0;
+        let inside_right = bands.winding((mid.0 - left.0, mid.1 - left.1)) > 0;
+
+        // Keep the edge with the area on its left, so outlines go counter-clockwise
+        match (inside_left, inside_right) {
+            (true, false) => edges.push((a, b)),
+            (false, true) => edges.push((b, a)),
+            _ => {}
+        }
+    }
+
+    chain(edges)
+}
+
+/// The outline of a ring with its edges moved outwards (to their right) by `delta`. Corners
+/// that the offset opens up get a join, and at the others the outline goes back through the
+/// corner, which makes a small loop that gets thrown away.
+fn raw_offset(ring: &[Point2], delta: f64, join: &Join) -> Vec<Point2> {
+    let n = ring.len();
+    let normal = |a: Point2, b: Point2| {
+        let d = sub(b, a);
+        let len = length(d);
+        (d.1 / len, -d.0 / len)
+    };
+    let moved = |p: Point2, n: Point2| (p.0 + n.0 * delta, p.1 + n.1 * delta);
+
+    let mut out = Vec::new();
+    for i in 0..n {
+        let (prev, p, next) = (ring[(i + n - 1) % n], ring[i], ring[(i + 1) % n]);
+        let (n1, n2) = (normal(prev, p), normal(p, next));
+        let (o1, o2) = (moved(p, n1), moved(p, n2));
+
+        let turn = cross(sub(p, prev), sub(next, p));
+        if turn == 0.0 && n1 == n2 {
+            out.push(o1);
+            continue;
+        }
+        if turn * delta < 0.0 {
+            out.extend(vec![o1, p, o2]);
+            continue;
+        }
+
+        match join {
+            Join::Round(segments) => {
+                let start = (o1.1 - p.1).atan2(o1.0 - p.0);
+                let mut sweep = (o2.1 - p.1).atan2(o2.0 - p.0) - start;
+                // Growing goes around the corner counter-clockwise, shrinking clockwise
+                if delta > 0.0 && sweep < 0.0 {
+                    sweep += 2.0 * PI;
+                } else if delta < 0.0 && sweep > 0.0 {
+                    sweep -= 2.0 * PI;
+                }
+
+                let steps = ((sweep.abs() / (2.0 * PI) * *segments as f64).ceil() as usize).max(1);
+                out.push(o1);
+                for step in 1..steps {
+                    let a = start + sweep * step as f64 / steps as f64;
+                    out.push((p.0 + delta.abs() * a.cos(), p.1 + delta.abs() * a.sin()));
+                }
+                out.push(o2);
+            }
+            Join::Miter => {
+                // Where the moved edges meet, unless the corner is so sharp that they barely do
+                let along = 1.0 + n1.0 * n2.0 + n1.1 * n2.1;
+                if along < 1e-9 {
+                    out.extend(vec![o1, o2]);
+                } else {
+                    out.push(moved(p, ((n1.0 + n2.0) / along, (n1.1 + n2.1) / along)));
+                }
+            }
+            Join::Chamfer => out.extend(vec![o1, o2]),
+        }
+    }
+
+    out
+}
+
+/// Splits the segments wherever they cross each other. Going through them from left to right,
+/// each segment is only compared with the ones it overlaps in x.
+fn split_segments(segments: &[(Point2, Point2)]) -> Vec<(Point2, Point2)> {
+    let mut cuts: Vec<Vec<(f64, Point2)>> = segments.iter().map(|_| Vec::new()).collect();
+
+    let min_x = |i: usize| {
+        let (a, b) = segments[i];
+        a.0.min(b.0)
+    };
+    let max_x = |i: usize| {
+        let (a, b) = segments[i];
+        a.0.max(b.0)
+    };
+    let mut order: Vec<usize> = (0..segments.len()).collect();
+    order.sort_by(|&i, &j| min_x(i).total_cmp(&min_x(j)));
+
+    for (k, &i) in order.iter().enumerate() {
+        for &j in order[k + 1..].iter().take_while(|&&j| min_x(j) <= max_x(i)) {
+            let (a, b) = segments[i];
+            let (c, d) = segments[j];
+            let (r, s) = (sub(b, a), sub(d, c));
+            let denominator = cross(r, s);
+            if denominator == 0.0 {
+                continue;
+            }
+
+            let t = cross(sub(c, a), s) / denominator;
+            let u = cross(sub(c, a), r) / denominator;
+            if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
+                // The same point for both, so that the pieces can be joined up again
+                let p = (a.0 + r.0 * t, a.1 + r.1 * t);
+                cuts[i].push((t, p));
+                cuts[j].push((u, p));
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    for (&(a, b), cuts) in segments.iter().zip(cuts.iter_mut()) {
+        cuts.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+        let mut from = a;
+        for &(t, p) in cuts.iter() {
+            if t > 0.0 && t < 1.0 && p != from {
+                out.push((from, p));
+                from = p;
+            }
+        }
+        if from != b {
+            out.push((from, b));
+        }
+    }
+
+    out
+}
+
+/// The segments sorted into horizontal bands by the heights they span, so that finding how many
+/// times they wind around a point only looks at the segments in the band of the point
+struct Bands<'a> {
+    segments: &'a [(Point2, Point2)],
+    min: f64,
+    height: f64,
+    bands: Vec<Vec<usize>>,
+}
+
+impl<'a> Bands<'a> {
+    fn new(segments: &'a [(Point2, Point2)]) -> Bands<'a> {
+        let (min, max) = segments
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), (a, b)| {
+                (min.min(a.1).min(b.1), max.max(a.1).max(b.1))
+            });
+
+        let mut bands = Bands {
+            segments,
+            min,
+            height: ((max - min) / segments.len() as f64).max(f64::MIN_POSITIVE),
+            bands: vec![Vec::new(); segments.len().max(1)],
+        };
+        for (i, (a, b)) in segments.iter().enumerate() {
+            let (from, to) = (bands.band(a.1.min(b.1)), bands.band(a.1.max(b.1)));
+            for band in &mut bands.bands[from..=to] {
+                band.push(i);
+            }
+        }
+
+        bands
+    }
+
+    /// The band a height is in, or the nearest one for heights outside of all of them
+    fn band(&self, y: f64) -> usize {
+        // Heights below the bands are cast to 0
+        (((y - self.min) / self.height) as usize).min(self.bands.len() - 1)
+    }
+
+    fn winding(&self, p: Point2) -> i32 {
+        plane::winding(
+            self.bands[self.band(p.1)]
+                .iter()
+                .map(|&i| &self.segments[i]),
+            p,
+        )
+    }
+}
+
+/// Joins up edges into rings, by following each edge to the one starting where it ends
+fn chain(edges: Vec<(Point2, Point2)>) -> (Vec<Point2>, Vec<Vec<usize>>) {
+    let key = |p: Point2| (p.0.to_bits(), p.1.to_bits());
+
+    let mut starting: HashMap<(u64, u64), Vec<usize>> = HashMap::new();
+    for (i, (a, _)) in edges.iter().enumerate() {
+        starting.entry(key(*a)).or_default().push(i);
+    }
+
+    let mut used = vec![false; edges.len()];
+    let mut points = Vec::new();
+    let mut paths = Vec::new();
+    for first in 0..edges.len() {
+        if used[first] {
+            continue;
+        }
+
+        let mut ring = Vec::new();
+        let mut current = first;
+        loop {
+            used[current] = true;
+            ring.push(edges[current].0);
+
+            let end = key(edges[current].1);
+            match starting
+                .get(&end)
+                .and_then(|next| next.iter().find(|&&i| !used[i]))
+            {
+                Some(&next) => current = next,
+                None => break,
+            }
+        }
+
+        if ring.len() >= 3 {
+            paths.push((points.len()..points.len() + ring.len()).collect());
+            points.extend(ring);
+        }
+    }
+
+    (points, paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(min: f64, max: f64) -> Vec<Point2> {
+        vec![(min, min), (max, min), (max, max), (min, max)]
+    }
+
+    /// The signed area and the number of corners of every ring of the offset polygon, largest
+    /// first
+    fn rings(polygon: &Polygon, delta: f64, join: &Join) -> Vec<(f64, usize)> {
+        let (points, paths) = offset(polygon, delta, join);
+        let mut rings: Vec<(f64, usize)> = paths
+            .iter()
+            .map(|path| {
+                let ring: Vec<Point2> = path.iter().map(|&i| points[i]).collect();
+                (plane::area(&ring), ring.len())
+            })
+            .collect();
+        rings.sort_by(|a, b| b.0.total_cmp(&a.0));
+        rings
+    }
+
+    fn assert_rings(got: Vec<(f64, usize)>, expected: &[(f64, usize)]) {
+        assert_eq!(got.len(), expected.len(), "{:?}", got);
+        for (g, e) in got.iter().zip(expected) {
+            assert!((g.0 - e.0).abs() < 1e-9 && g.1 == e.1, "{:?}", got);
+        }
+    }
+
+    #[test]
+    fn grows_and_shrinks_a_square_with_every_join() {
+        let polygon = Polygon(square(0.0, 2.0), None, None);
+
+        // Every corner gets a quarter of a circle of 16 segments, which is 4 segments
+        let corner = 4.0 * 0.5 * (PI / 8.0).sin();
+        assert_rings(
+            rings(&polygon, 1.0, &Join::Round(16)),
+            &[(4.0 + 8.0 + 4.0 * corner, 20)],
+        );
+        assert_rings(rings(&polygon, 1.0, &Join::Miter), &[(16.0, 4)]);
+        assert_rings(rings(&polygon, 1.0, &Join::Chamfer), &[(14.0, 8)]);
+
+        // Shrinking doesn't open up any corners, so the joins don't matter
+        for join in &[Join::Round(16), Join::Miter, Join::Chamfer] {
+            assert_rings(rings(&polygon, -0.5, join), &[(1.0, 4)]);
+        }
+    }
+
+    #[test]
+    fn grows_many_sided_polygons() {
+        // The moved edges of a circle and the arcs joining them make a bigger circle
+        let circle = Polygon(crate::eval::circle_points(2000, 10.0), None, None);
+        let got = rings(&circle, 1.0, &Join::Round(2000));
+        assert_eq!(got.len(), 1);
+        assert!(got[0].1 >= 4000, "{:?}", got);
+        assert!((got[0].0 - PI * 121.0).abs() < 0.01, "{:?}", got);
+    }
+
+    #[test]
+    fn shrinks_polygons_away() {
+        let polygon = Polygon(square(0.0, 2.0), None, None);
+        for join in &[Join::Round(16), Join::Miter, Join::Chamfer] {
+            let (points, paths) = offset(&polygon, -1.5, join);
+            assert!(paths.is_empty(), "{:?}", points);
+        }
+    }
+
+    #[test]
+    fn offsets_holes_the_other_way() {
+        let mut points = square(0.0, 10.0);
+        points.extend(square(3.0, 7.0));
+        let polygon = Polygon(points, Some(vec![vec![0, 1, 2, 3], vec![4, 5, 6, 7]]), None);
+
+        // Growing the polygon shrinks its holes, which stay clockwise
+        assert_rings(rings(&polygon, 1.0, &Join::Miter), &[(144.0, 4), (-4.0, 4)]);
+        // And shrinking it grows them
+        assert_rings(
+            rings(&polygon, -1.0, &Join::Miter),
+            &[(64.0, 4), (-36.0, 4)],
+        );
+        // Until they swallow the polygon, leaving nothing
+        assert_rings(rings(&polygon, -2.0, &Join::Miter), &[]);
+    }
+}
@@ -0,0 +1,95 @@
+// Geometry in the plane: points used as vectors, and the rings polygons are made of. Used both for
+// offsetting polygons and by the backends that turn polygons into meshes.
+
+use super::Polygon;
+
+pub type Point2 = (f64, f64);
+
+pub fn sub(a: Point2, b: Point2) -> Point2 {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+pub fn cross(a: Point2, b: Point2) -> f64 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+pub fn length(a: Point2) -> f64 {
+    (a.0 * a.0 + a.1 * a.1).sqrt()
+}
+
+/// The signed area of a ring, positive if its points are counter-clockwise
+pub fn area(ring: &[Point2]) -> f64 {
+    (0..ring.len())
+        .map(|i| cross(ring[i], ring[(i + 1) % ring.len()]))
+        .sum::<f64>()
+        / 2.0
+}
+
+/// How many times the segments (which make up closed rings) wind around a point,
+/// counter-clockwise
+pub fn winding<'a>(segments: impl IntoIterator<Item = &'a (Point2, Point2)>, p: Point2) -> i32 {
+    segments
+        .into_iter()
+        .map(|&(a, b)| {
+            let side = cross(sub(b, a), sub(p, a));
+            if a.1 <= p.1 && p.1 < b.1 && side > 0.0 {
+                1
+            } else if b.1 <= p.1 && p.1 < a.1 && side < 0.0 {
+                -1
+            } else {
+                0
+            }
+        })
+        .sum()
+}
+
+/// Checks if a point is inside a ring, by the even-odd rule
+pub fn ring_contains(ring: &[Point2], p: Point2) -> bool {
+    let mut inside = false;
+    for (i, a) in ring.iter().enumerate() {
+        let b = ring[(i + 1) % ring.len()];
+        if (a.1 > p.1) != (b.1 > p.1) && p.0 < a.0 + (p.1 - a.1) * (b.0 - a.0) / (b.1 - a.1) {
+            inside = !inside;
+        }
+    }
+
+    inside
+}
+
+/// The rings of a polygon with their depth (the number of other rings they are inside of),
+/// turned so that outlines (even depth) are counter-clockwise and holes (odd depth) clockwise.
+/// Repeated points are left out, and so are rings without any area.
+pub fn oriented_rings(polygon: &Polygon) -> Vec<(Vec<Point2>, usize)> {
+    let rings: Vec<Vec<Point2>> = polygon
+        .rings()
+        .into_iter()
+        .map(|mut r| {
+            r.dedup();
+            if r.len() > 1 && r.first() == r.last() {
+                r.pop();
+            }
+            r
+        })
+        .filter(|r| area(r) != 0.0)
+        .collect();
+
+    rings
+        .iter()
+        .enumerate()
+        .map(|(i, ring)| {
+            // The middle of an edge, since corners may be shared with other rings
+            let p = ((ring[0].0 + ring[1].0) / 2.0, (ring[0].1 + ring[1].1) / 2.0);
+            let depth = rings
+                .iter()
+                .enumerate()
+                .filter(|&(j, other)| j != i && ring_contains(other, p))
+                .count();
+
+            let mut ring = ring.clone();
+            if (area(&ring) > 0.0) != (depth % 2 == 0) {
+                ring.reverse();
+            }
+            (ring, depth)
+        })
+        .collect()
+}
@@ -0,0 +1,43 @@
+#[derive(Clone, Debug, PartialEq)]
+pub enum Transform {
+    Translate((f64, f64, f64)),
+    /// Rotation around the x, y and z axes (in that order), in degrees
+    Rotate((f64, f64, f64)),
+    /// Scaling along the x, y and z axes. Negative factors mirror the values.
+    Scale((f64, f64, f64)),
+}
+
+impl Transform {
+    /// Maps a point from the space of the transformed values into the space of their parent
+    pub fn apply(&self, p: (f64, f64, f64)) -> (f64, f64, f64) {
+        match self {
+            Transform::Translate(o) => (p.0 + o.0, p.1 + o.1, p.2 + o.2),
+            Transform::Rotate(a) => rotate_z(rotate_y(rotate_x(p, a.0), a.1), a.2),
+            Transform::Scale(f) => (p.0 * f.0, p.1 * f.1, p.2 * f.2),
+        }
+    }
+
+    /// The inverse of `apply`
+    pub fn apply_inverse(&self, p: (f64, f64, f64)) -> (f64, f64, f64) {
+        match self {
+            Transform::Translate(o) => (p.0 - o.0, p.1 - o.1, p.2 - o.2),
+            Transform::Rotate(a) => rotate_x(rotate_y(rotate_z(p, -a.2), -a.1), -a.0),
+            Transform::Scale(f) => (p.0 / f.0, p.1 / f.1, p.2 / f.2),
+        }
+    }
+}
+
+fn rotate_x(p: (f64, f64, f64), degrees: f64) -> (f64, f64, f64) {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    (p.0, p.1 * cos - p.2 * sin, p.1 * sin + p.2 * cos)
+}
+
+fn rotate_y(p: (f64, f64, f64), degrees: f64) -> (f64, f64, f64) {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    (p.0 * cos + p.2 * sin, p.1, -p.0 * sin + p.2 * cos)
+}
+
+fn rotate_z(p: (f64, f64, f64), degrees: f64) -> (f64, f64, f64) {
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    (p.0 * cos - p.1 * sin, p.0 * sin + p.1 * cos, p.2)
+}
@@ -1,13 +1,165 @@
 use super::*;
 
-pub fn cast_number(a: &EvaluatedValue) -> f64 {
+/// Numbers too large to store (like `1e400`) and the results of invalid calculations are rejected
+/// here, before they can get to the backends
+pub fn cast_number(a: &EvaluatedValue, span: Span) -> Result<f64, EvalError> {
     match a {
-        EvaluatedValue::None => 0.0,
-        EvaluatedValue::True => 1.0,
-        EvaluatedValue::Number(v) => *v,
-        EvaluatedValue::Vector(_) => todo!("Vector can not be cast into a number (yet)"),
-        EvaluatedValue::Native(_) => {
-            unimplemented!("A native value (like polygon or extrude) can not be cast into a number")
+        EvaluatedValue::None => Ok(0.0),
+        EvaluatedValue::True => Ok(1.0),
+        EvaluatedValue::Number(v) if !v.is_finite() => Err(EvalError::InvalidArgument(
+            format!("Expected a finite number, got {}", v),
+            span,
+        )),
+        EvaluatedValue::Number(v) => Ok(*v),
+        v => Err(EvalError::UnexpectedValue {
+            expected: "a number".to_owned(),
+            got: Box::new(v.clone()),
+            span,
+        }),
+    }
+}
+
+/// Whether a value counts as true, like for the condition of `?`
+pub fn cast_condition(a: &EvaluatedValue, span: Span) -> Result<bool, EvalError> {
+    match a {
+        EvaluatedValue::None => Ok(false),
+        EvaluatedValue::True => Ok(true),
+        EvaluatedValue::Number(n) => Ok(*n != 0.0),
+        EvaluatedValue::Vector(_) => Ok(true),
+        v => Err(EvalError::UnexpectedValue {
+            expected: "a condition".to_owned(),
+            got: Box::new(v.clone()),
+            span,
+        }),
+    }
+}
+
+pub fn cast_vector3(a: &EvaluatedValue, span: Span) -> Result<(f64, f64, f64), EvalError> {
+    match a {
+        EvaluatedValue::Vector(v) if v.len() == 3 => Ok((
+            cast_number(&v[0], span)?,
+            cast_number(&v[1], span)?,
+            cast_number(&v[2], span)?,
+        )),
+        v => Err(EvalError::UnexpectedValue {
+            expected: "a 3D-vector".to_owned(),
+            got: Box::new(v.clone()),
+            span,
+        }),
+    }
+}
+
+pub fn evaluate_number(env: &mut EvalEnvironment, arg: &parse::Value) -> Result<f64, EvalError> {
+    cast_number(&evaluate_expression(arg, env)?, arg.span())
+}
+
+/// Makes sure a function got between `min` and `max` (inclusive) arguments. No `max` means there
+/// is no upper limit.
+pub fn expect_args(
+    function: &str,
+    span: Span,
+    args: &[parse::Value],
+    min: usize,
+    max: Option<usize>,
+) -> Result<(), EvalError> {
+    expect_count(function, span, args.len(), min, max)
+}
+
+/// Like `expect_args`, for arguments that have already been evaluated
+pub fn expect_count(
+    function: &str,
+    span: Span,
+    got: usize,
+    min: usize,
+    max: Option<usize>,
+) -> Result<(), EvalError> {
+    let ok = got >= min && max.map(|max| got <= max).unwrap_or(true);
+    if ok {
+        return Ok(());
+    }
+
+    Err(EvalError::ArgumentCount {
+        function: function.to_owned(),
+        expected: match max {
+            None => format!("at least {}", min),
+            Some(max) if max == min => format!("{}", min),
+            Some(max) => format!("{} to {}", min, max),
+        },
+        got,
+        span,
+    })
+}
+
+/// Separates keyword arguments (like `:twist 90`) from the other arguments, which keep their
+/// order. Only the given keywords (without the colon) are allowed.
+pub fn split_keywords<'a>(
+    function: &str,
+    args: &'a [parse::Value],
+    keywords: &[&str],
+) -> Result<(Vec<parse::Value>, HashMap<&'a str, &'a parse::Value>), EvalError> {
+    let mut positional = Vec::new();
+    let mut found = HashMap::new();
+
+    let mut args = args.iter();
+    while let Some(a) = args.next() {
+        let keyword = match a {
+            parse::Value::Identifier(name, span) if name.starts_with(':') => {
+                let keyword = &name[1..];
+                if !keywords.contains(&keyword) {
+                    return Err(EvalError::InvalidArgument(
+                        format!(
+                            "{} doesn't take {} (it takes :{})",
+                            function,
+                            name,
+                            keywords.join(", :")
+                        ),
+                        *span,
+                    ));
+                }
+                keyword
+            }
+            _ => {
+                positional.push(a.clone());
+                continue;
+            }
+        };
+
+        match args.next() {
+            Some(v) => {
+                found.insert(keyword, v);
+            }
+            None => {
+                return Err(EvalError::InvalidArgument(
+                    format!("Expected a value after :{}", keyword),
+                    a.span(),
+                ))
+            }
         }
     }
+
+    Ok((positional, found))
+}
+
+/// Evaluates every expression and collects the native values (like polygon or extrude) they
+/// produce. Expressions evaluating to nil (like `set`) are skipped.
+pub fn evaluate_natives(
+    env: &mut EvalEnvironment,
+    args: &[parse::Value],
+) -> Result<Vec<NativeValue>, EvalError> {
+    let mut out = Vec::new();
+    for a in args {
+        match evaluate_expression(a, env)? {
+            EvaluatedValue::Native(n) => out.push(n),
+            EvaluatedValue::None => {}
+            v => {
+                return Err(EvalError::UnexpectedValue {
+                    expected: "a native value (like polygon or extrude)".to_owned(),
+                    got: Box::new(v),
+                    span: a.span(),
+                })
+            }
+        }
+    }
+
+    Ok(out)
 }
@@ -1,8 +1,26 @@
-use std::fs;
-use std::io;
+/// A range in the source code. Lines and columns start at 1, and the end is exclusive.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Span {
+    /// A span covering everything from the start of `self` to the end of `other`
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            line: self.line,
+            column: self.column,
+            end_line: other.end_line,
+            end_column: other.end_column,
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Clone)]
-pub enum InputToken {
+pub enum TokenKind {
     Comment(String),
     StartParen,
     EndParen,
@@ -10,62 +28,111 @@ pub enum InputToken {
     Identifier(String),
 }
 
-pub fn lex_file(path: &str) -> io::Result<Vec<InputToken>> {
-    let src = fs::read_to_string(path)?;
+#[derive(Debug, PartialEq, Clone)]
+pub struct InputToken {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+pub fn lex(src: &str) -> Vec<InputToken> {
     let mut out = Vec::new();
 
-    let append_src = |src: String, out: &mut Vec<InputToken>| {
+    // Where the token that is currently being read starts
+    let mut start = (1, 1);
+
+    let span_of = |src: &str, start: (usize, usize)| Span {
+        line: start.0,
+        column: start.1,
+        end_line: start.0,
+        end_column: start.1 + src.chars().count(),
+    };
+
+    let append_src = |src: String, start: (usize, usize), out: &mut Vec<InputToken>| {
         let src = src.trim().to_owned();
-        if src == "" {
+        if src.is_empty() {
             return;
         }
 
+        let span = span_of(&src, start);
+        // Rust also reads words like `nan` and `inf` as numbers, which are left as identifiers
+        let is_word = src
+            .chars()
+            .any(|c| c.is_alphabetic() && c != 'e' && c != 'E');
         match src.parse::<f64>() {
-            Ok(num) => out.push(InputToken::Number(num)),
-            Err(_) => out.push(InputToken::Identifier(src)),
+            Ok(num) if !is_word => out.push(InputToken {
+                kind: TokenKind::Number(num),
+                span,
+            }),
+            _ => out.push(InputToken {
+                kind: TokenKind::Identifier(src),
+                span,
+            }),
         }
     };
 
-    let append_comment = |c: String, out: &mut Vec<InputToken>| {
+    let append_comment = |c: String, start: (usize, usize), out: &mut Vec<InputToken>| {
         let c = c.trim().to_owned();
-        if c == "" {
+        if c.is_empty() {
             return;
         }
-        out.push(InputToken::Comment(c));
+
+        let span = span_of(&c, start);
+        out.push(InputToken {
+            kind: TokenKind::Comment(c),
+            span,
+        });
     };
 
     let mut current = String::new();
     let mut is_in_comment = false;
+    let (mut line, mut column) = (1, 1);
     for ch in src.chars() {
         if ch == ';' && !is_in_comment {
             is_in_comment = true;
-            append_src(current, &mut out);
+            append_src(current, start, &mut out);
             current = String::new();
         } else if ch == '\n' && is_in_comment {
             is_in_comment = false;
-            append_comment(current, &mut out);
+            append_comment(current, start, &mut out);
             current = String::new();
         }
 
         if (ch.is_whitespace() || ch == '(' || ch == ')') && !is_in_comment {
-            append_src(current, &mut out);
+            append_src(current, start, &mut out);
             current = String::new();
         }
 
         if ch == '(' && !is_in_comment {
-            out.push(InputToken::StartParen);
+            out.push(InputToken {
+                kind: TokenKind::StartParen,
+                span: span_of("(", (line, column)),
+            });
         } else if ch == ')' && !is_in_comment {
-            out.push(InputToken::EndParen);
+            out.push(InputToken {
+                kind: TokenKind::EndParen,
+                span: span_of(")", (line, column)),
+            });
         } else {
+            // Tokens are trimmed, so they start at their first non-whitespace character
+            if current.trim().is_empty() && !ch.is_whitespace() {
+                start = (line, column);
+            }
             current.push(ch);
         }
+
+        if ch == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
     }
 
     if is_in_comment {
-        append_comment(current, &mut out);
+        append_comment(current, start, &mut out);
     } else {
-        append_src(current, &mut out);
+        append_src(current, start, &mut out);
     }
 
-    Ok(out)
+    out
 }
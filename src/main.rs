@@ -1,30 +1,187 @@
 use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process;
 
+use config::{Backend, Config};
+
+mod config;
+mod diagnostic;
 mod eval;
 mod lex;
 mod out;
 mod parse;
 
-//TODO Get these from arguments
-static SCALE: i32 = 10;
-static ORIGIN: (isize, isize, isize) = (0, 0, 4);
-
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    //TODO Move this to `parse_config()`: https://doc.rust-lang.org/book/ch12-03-improving-error-handling-and-modularity.html
-    let input_file_path = args.get(1).expect("No input file.");
+    let config = match Config::build(env::args().skip(1)) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, config::USAGE);
+            process::exit(2);
+        }
+    };
+
+    if config.help {
+        println!("{}", config::USAGE);
+        return;
+    }
+
+    let src = match read_input(&config.input) {
+        Ok(src) => src,
+        Err(e) => {
+            eprintln!("error: Could not read {}: {}", config.input, e);
+            process::exit(1);
+        }
+    };
+
+    let materials = match &config.materials {
+        None => out::Materials::default(),
+        Some(path) => match fs::read_to_string(path) {
+            Ok(m) => match out::Materials::parse(&m) {
+                Ok(materials) => materials,
+                Err(e) => {
+                    eprintln!("error: Could not parse {}: {}", path, e);
+                    process::exit(1);
+                }
+            },
+            Err(e) => {
+                eprintln!("error: Could not read {}: {}", path, e);
+                process::exit(1);
+            }
+        },
+    };
+
+    let lexed = lex::lex(&src);
+
+    let parsed = match parse::parse(lexed) {
+        Ok(parsed) => parsed,
+        Err(e) => fail(&config.input, &src, &e.to_string(), e.span()),
+    };
+
+    let out = match eval::evaluate(parsed) {
+        Ok(out) => out,
+        Err(e) => fail(&config.input, &src, &e.to_string(), e.span()),
+    };
+
+    if config.check {
+        return;
+    }
 
-    let lexed = lex::lex_file(input_file_path).unwrap();
+    for backend in &config.backends {
+        if let Err(e) = write_backend(&config, *backend, &out, &materials) {
+            eprintln!("error: Could not write the output: {}", e);
+            process::exit(1);
+        }
+    }
+}
 
-    let parsed = parse::parse(lexed).unwrap();
+fn write_backend(
+    config: &Config,
+    backend: Backend,
+    out: &[eval::EvaluatedValue],
+    materials: &out::Materials,
+) -> io::Result<()> {
+    match backend {
+        Backend::Scad => write_output(
+            config.output_of(backend),
+            &out::process_out_scad(out, materials),
+        ),
+        Backend::Mc => match out::process_out_mc(
+            out,
+            config.scale,
+            Some(config.origin),
+            &config.material,
+            materials,
+        ) {
+            Ok(mc_out) => {
+                write_output(config.output_of(backend), &mc_out.0)?;
+                write_output(&config.delete_output, &mc_out.1)
+            }
+            Err(e) => fail_backend(&e),
+        },
+        Backend::Structure => {
+            match out::process_out_structure(out, config.scale, &config.material, materials) {
+                Ok(structure_out) => write_binary_output(config.output_of(backend), &structure_out),
+                Err(e) => fail_backend(&e),
+            }
+        }
+        Backend::Schematic => match out::process_out_schematic(
+            out,
+            config.scale,
+            Some(config.origin),
+            &config.material,
+            materials,
+        ) {
+            Ok(schematic_out) => write_binary_output(config.output_of(backend), &schematic_out),
+            Err(e) => fail_backend(&e),
+        },
+        Backend::Obj => {
+            // The materials go in an MTL file next to the OBJ file, unless it is written to stdout
+            let output = config.output_of(backend);
+            let mtl_path = Some(Path::new(output).with_extension("mtl")).filter(|_| output != "-");
+            let mtllib = mtl_path
+                .as_ref()
+                .and_then(|p| p.file_name())
+                .map(|n| n.to_string_lossy().into_owned());
 
-    let out = eval::evaluate(parsed).unwrap();
-    // println!("Out: {:?}", out);
+            match out::process_out_obj(out, materials, mtllib.as_deref()) {
+                Ok((obj_out, mtl_out)) => {
+                    write_output(output, &obj_out)?;
+                    match (mtl_path, mtl_out) {
+                        (Some(p), Some(mtl_out)) => fs::write(p, mtl_out),
+                        _ => Ok(()),
+                    }
+                }
+                Err(e) => fail_backend(&e),
+            }
+        }
+        Backend::Stl => match out::process_out_stl(out, config.ascii_stl) {
+            Ok(stl_out) => write_binary_output(config.output_of(backend), &stl_out),
+            Err(e) => fail_backend(&e),
+        },
+    }
+}
 
-    let scad_out = out::process_out_scad(&out);
-    std::fs::write("out.scad", scad_out).unwrap();
+/// Reads the source code from a file, or from stdin if the path is `-`
+fn read_input(path: &str) -> io::Result<String> {
+    if path == "-" {
+        let mut src = String::new();
+        io::stdin().read_to_string(&mut src)?;
+        Ok(src)
+    } else {
+        fs::read_to_string(path)
+    }
+}
+
+/// Writes the output to a file, or to stdout if the path is `-`
+fn write_output(path: &str, contents: &str) -> io::Result<()> {
+    if path == "-" {
+        let mut stdout = io::stdout();
+        writeln!(stdout, "{}", contents)?;
+        stdout.flush()
+    } else {
+        fs::write(path, contents)
+    }
+}
+
+/// Writes binary output to a file, or to stdout if the path is `-`
+fn write_binary_output(path: &str, contents: &[u8]) -> io::Result<()> {
+    if path == "-" {
+        let mut stdout = io::stdout();
+        stdout.write_all(contents)?;
+        stdout.flush()
+    } else {
+        fs::write(path, contents)
+    }
+}
+
+fn fail_backend(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(1);
+}
 
-    let mc_out = out::process_out_mc(&out, SCALE, Some(ORIGIN));
-    std::fs::write("out.mccmd", mc_out.0).unwrap();
-    std::fs::write("del.mccmd", mc_out.1).unwrap();
+fn fail(path: &str, src: &str, message: &str, span: lex::Span) -> ! {
+    eprintln!("{}", diagnostic::render(path, src, message, span));
+    process::exit(1);
 }
@@ -0,0 +1,312 @@
+// Boolean operations on meshes, using binary space partitioning trees. This is a port of the
+// algorithm used by csg.js: https://github.com/evanw/csg.js
+
+use std::mem;
+
+use super::geom::{self, Point3};
+use super::mesh::Face;
+
+/// How far from a plane a point may be while still being considered on it
+const EPSILON: f64 = 1e-5;
+
+pub fn union(a: &[Face], b: &[Face]) -> Vec<Face> {
+    let mut a = Node::new(to_polygons(a));
+    let mut b = Node::new(to_polygons(b));
+
+    a.clip_to(&b);
+    b.clip_to(&a);
+    b.invert();
+    b.clip_to(&a);
+    b.invert();
+    a.build(b.all_polygons());
+
+    from_polygons(a.all_polygons())
+}
+
+pub fn difference(a: &[Face], b: &[Face]) -> Vec<Face> {
+    let mut a = Node::new(to_polygons(a));
+    let mut b = Node::new(to_polygons(b));
+
+    a.invert();
+    a.clip_to(&b);
+    b.clip_to(&a);
+    b.invert();
+    b.clip_to(&a);
+    b.invert();
+    a.build(b.all_polygons());
+    a.invert();
+
+    from_polygons(a.all_polygons())
+}
+
+pub fn intersection(a: &[Face], b: &[Face]) -> Vec<Face> {
+    let mut a = Node::new(to_polygons(a));
+    let mut b = Node::new(to_polygons(b));
+
+    a.invert();
+    b.clip_to(&a);
+    b.invert();
+    a.clip_to(&b);
+    b.clip_to(&a);
+    a.build(b.all_polygons());
+    a.invert();
+
+    from_polygons(a.all_polygons())
+}
+
+fn to_polygons(faces: &[Face]) -> Vec<Polygon> {
+    faces
+        .iter()
+        .filter_map(|f| Polygon::new(f.points.clone(), f.material.clone()))
+        .collect()
+}
+
+fn from_polygons(polygons: Vec<Polygon>) -> Vec<Face> {
+    polygons
+        .into_iter()
+        .map(|p| Face {
+            points: p.vertices,
+            material: p.material,
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Plane {
+    normal: Point3,
+    w: f64,
+}
+
+impl Plane {
+    fn flip(&mut self) {
+        self.normal = geom::mul(self.normal, -1.0);
+        self.w = -self.w;
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Polygon {
+    vertices: Vec<Point3>,
+    plane: Plane,
+    /// Carried along to the parts of the polygon when it is split
+    material: Option<String>,
+}
+
+impl Polygon {
+    /// Creates a polygon from convex, planar vertices, or `None` if they have no area
+    fn new(vertices: Vec<Point3>, material: Option<String>) -> Option<Polygon> {
+        let normal = geom::polygon_normal(&vertices);
+        let length = geom::length(normal);
+        if vertices.len() < 3 || length < EPSILON * EPSILON {
+            return None;
+        }
+
+        let normal = geom::mul(normal, 1.0 / length);
+        let w = geom::dot(normal, vertices[0]);
+        Some(Polygon {
+            vertices,
+            plane: Plane { normal, w },
+            material,
+        })
+    }
+
+    fn flip(&mut self) {
+        self.vertices.reverse();
+        self.plane.flip();
+    }
+}
+
+/// Where a polygon ended up when split by a plane
+#[derive(Default)]
+struct Split {
+    coplanar_front: Vec<Polygon>,
+    coplanar_back: Vec<Polygon>,
+    front: Vec<Polygon>,
+    back: Vec<Polygon>,
+}
+
+const COPLANAR: u8 = 0;
+const FRONT: u8 = 1;
+const BACK: u8 = 2;
+const SPANNING: u8 = 3;
+
+fn split_polygon(plane: &Plane, polygon: Polygon, out: &mut Split) {
+    let types: Vec<u8> = polygon
+        .vertices
+        .iter()
+        .map(|v| {
+            let t = geom::dot(plane.normal, *v) - plane.w;
+            if t < -EPSILON {
+                BACK
+            } else if t > EPSILON {
+                FRONT
+            } else {
+                COPLANAR
+            }
+        })
+        .collect();
+    let polygon_type = types.iter().fold(COPLANAR, |acc, t| acc | t);
+
+    match polygon_type {
+        COPLANAR => {
+            if geom::dot(plane.normal, polygon.plane.normal) > 0.0 {
+                out.coplanar_front.push(polygon);
+            } else {
+                out.coplanar_back.push(polygon);
+            }
+        }
+        FRONT => out.front.push(polygon),
+        BACK => out.back.push(polygon),
+        _ => {
+            let mut front = Vec::new();
+            let mut back = Vec::new();
+
+            let n = polygon.vertices.len();
+            for i in 0..n {
+                let j = (i + 1) % n;
+                let (ti, tj) = (types[i], types[j]);
+                let (vi, vj) = (polygon.vertices[i], polygon.vertices[j]);
+
+                if ti != BACK {
+                    front.push(vi);
+                }
+                if ti != FRONT {
+                    back.push(vi);
+                }
+                if ti | tj == SPANNING {
+                    let t = (plane.w - geom::dot(plane.normal, vi))
+                        / geom::dot(plane.normal, geom::sub(vj, vi));
+                    let v = geom::lerp(vi, vj, t);
+                    front.push(v);
+                    back.push(v);
+                }
+            }
+
+            if front.len() >= 3 {
+                out.front.push(Polygon {
+                    vertices: front,
+                    plane: polygon.plane,
+                    material: polygon.material.clone(),
+                });
+            }
+            if back.len() >= 3 {
+                out.back.push(Polygon {
+                    vertices: back,
+                    plane: polygon.plane,
+                    material: polygon.material,
+                });
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Node {
+    plane: Option<Plane>,
+    front: Option<Box<Node>>,
+    back: Option<Box<Node>>,
+    polygons: Vec<Polygon>,
+}
+
+impl Node {
+    fn new(polygons: Vec<Polygon>) -> Node {
+        let mut node = Node::default();
+        node.build(polygons);
+        node
+    }
+
+    /// Turns the solid inside out
+    fn invert(&mut self) {
+        for p in &mut self.polygons {
+            p.flip();
+        }
+        if let Some(plane) = &mut self.plane {
+            plane.flip();
+        }
+        if let Some(front) = &mut self.front {
+            front.invert();
+        }
+        if let Some(back) = &mut self.back {
+            back.invert();
+        }
+        mem::swap(&mut self.front, &mut self.back);
+    }
+
+    /// Removes the parts of the polygons that are inside of this tree
+    fn clip_polygons(&self, polygons: Vec<Polygon>) -> Vec<Polygon> {
+        let plane = match &self.plane {
+            None => return polygons,
+            Some(plane) => plane,
+        };
+
+        let mut split = Split::default();
+        for p in polygons {
+            split_polygon(plane, p, &mut split);
+        }
+
+        let mut front = split.coplanar_front;
+        front.extend(split.front);
+        let mut back = split.coplanar_back;
+        back.extend(split.back);
+
+        let mut out = match &self.front {
+            Some(node) => node.clip_polygons(front),
+            None => front,
+        };
+        if let Some(node) = &self.back {
+            out.extend(node.clip_polygons(back));
+        }
+
+        out
+    }
+
+    /// Removes the parts of this tree's polygons that are inside of `bsp`
+    fn clip_to(&mut self, bsp: &Node) {
+        self.polygons = bsp.clip_polygons(mem::take(&mut self.polygons));
+        if let Some(front) = &mut self.front {
+            front.clip_to(bsp);
+        }
+        if let Some(back) = &mut self.back {
+            back.clip_to(bsp);
+        }
+    }
+
+    fn all_polygons(&self) -> Vec<Polygon> {
+        let mut out = self.polygons.clone();
+        if let Some(front) = &self.front {
+            out.extend(front.all_polygons());
+        }
+        if let Some(back) = &self.back {
+            out.extend(back.all_polygons());
+        }
+
+        out
+    }
+
+    fn build(&mut self, polygons: Vec<Polygon>) {
+        if polygons.is_empty() {
+            return;
+        }
+
+        let plane = *self.plane.get_or_insert(polygons[0].plane);
+
+        let mut split = Split::default();
+        for p in polygons {
+            split_polygon(&plane, p, &mut split);
+        }
+
+        self.polygons.extend(split.coplanar_front);
+        self.polygons.extend(split.coplanar_back);
+
+        if !split.front.is_empty() {
+            self.front
+                .get_or_insert_with(Default::default)
+                .build(split.front);
+        }
+        if !split.back.is_empty() {
+            self.back
+                .get_or_insert_with(Default::default)
+                .build(split.back);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::cmp::Ordering;
+
+use super::eval;
+
+pub type Point3 = (f64, f64, f64);
+
+/// Orders numbers like `partial_cmp` does (with -0 equal to 0), but can't fail: NaN goes after
+/// every other number
+pub fn cmp(a: f64, b: f64) -> Ordering {
+    (a + 0.0).total_cmp(&(b + 0.0))
+}
+
+/// Orders points by x, then y and then z
+pub fn cmp_points(a: Point3, b: Point3) -> Ordering {
+    cmp(a.0, b.0).then(cmp(a.1, b.1)).then(cmp(a.2, b.2))
+}
+
+/// An axis-aligned box, as its minimum and maximum corner
+pub type Bounds = (Point3, Point3);
+
+/// The bounds of a transformed box (which may be larger than the box itself, for rotations)
+pub fn transform_bounds(transform: &eval::Transform, bounds: Bounds) -> Bounds {
+    let (min, max) = bounds;
+
+    let mut corners = Vec::new();
+    for &x in &[min.0, max.0] {
+        for &y in &[min.1, max.1] {
+            for &z in &[min.2, max.2] {
+                corners.push(transform.apply((x, y, z)));
+            }
+        }
+    }
+
+    bounds_of(&corners)
+}
+
+/// The smallest box containing all of the points
+pub fn bounds_of(points: &[Point3]) -> Bounds {
+    let mut min = (f64::INFINITY, f64::INFINITY, f64::INFINITY);
+    let mut max = (f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+
+    for p in points {
+        min = (min.0.min(p.0), min.1.min(p.1), min.2.min(p.2));
+        max = (max.0.max(p.0), max.1.max(p.1), max.2.max(p.2));
+    }
+
+    (min, max)
+}
+
+pub fn union_bounds(a: Bounds, b: Bounds) -> Bounds {
+    bounds_of(&[a.0, a.1, b.0, b.1])
+}
+
+pub fn add(a: Point3, b: Point3) -> Point3 {
+    (a.0 + b.0, a.1 + b.1, a.2 + b.2)
+}
+
+pub fn sub(a: Point3, b: Point3) -> Point3 {
+    (a.0 - b.0, a.1 - b.1, a.2 - b.2)
+}
+
+pub fn mul(a: Point3, f: f64) -> Point3 {
+    (a.0 * f, a.1 * f, a.2 * f)
+}
+
+pub fn dot(a: Point3, b: Point3) -> f64 {
+    a.0 * b.0 + a.1 * b.1 + a.2 * b.2
+}
+
+pub fn cross(a: Point3, b: Point3) -> Point3 {
+    (
+        a.1 * b.2 - a.2 * b.1,
+        a.2 * b.0 - a.0 * b.2,
+        a.0 * b.1 - a.1 * b.0,
+    )
+}
+
+pub fn length(a: Point3) -> f64 {
+    dot(a, a).sqrt()
+}
+
+/// The normal of a planar polygon (not normalized), using Newell's method, which works even if
+/// some of the points are collinear
+pub fn polygon_normal(points: &[Point3]) -> Point3 {
+    let mut normal = (0.0, 0.0, 0.0);
+    for (i, a) in points.iter().enumerate() {
+        let b = points[(i + 1) % points.len()];
+        normal = add(normal, cross(*a, b));
+    }
+
+    normal
+}
+
+/// The point a fraction `t` of the way from `a` to `b`
+pub fn lerp(a: Point3, b: Point3, t: f64) -> Point3 {
+    add(a, mul(sub(b, a), t))
+}
@@ -0,0 +1,142 @@
+// Convex hulls of points, in the plane (Andrew's monotone chain) and in space (adding one point at a
+// time to a hull that starts as a tetrahedron).
+
+use std::collections::HashSet;
+
+use super::eval::plane::{self, Point2};
+use super::geom::{self, Point3};
+use super::mesh::Face;
+
+/// How far outside of a face a point has to be to be added to the hull
+const EPSILON: f64 = 1e-9;
+
+fn turn(o: Point2, a: Point2, b: Point2) -> f64 {
+    plane::cross(plane::sub(a, o), plane::sub(b, o))
+}
+
+/// The corners of the convex hull, counter-clockwise. Points on its edges are left out.
+pub fn hull_2d(points: &[Point2]) -> Vec<Point2> {
+    let mut points = points.to_vec();
+    points.sort_by(|a, b| geom::cmp(a.0, b.0).then(geom::cmp(a.1, b.1)));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    // The lower half from left to right, then the upper half back
+    let mut hull: Vec<Point2> = Vec::new();
+    for _ in 0..2 {
+        let start = hull.len();
+        for &p in points.iter() {
+            while hull.len() >= start + 2
+                && turn(hull[hull.len() - 2], hull[hull.len() - 1], p) <= EPSILON
+            {
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        // The last point is the first one of the other half
+        hull.pop();
+        points.reverse();
+    }
+
+    hull
+}
+
+/// The faces (triangles facing outwards) of the convex hull, or none if the points are all in a
+/// plane
+pub fn hull_3d(points: &[Point3]) -> Vec<Face> {
+    let mut points = points.to_vec();
+    points.sort_by(|a, b| geom::cmp_points(*a, *b));
+    points.dedup();
+
+    let (start, rest) = match tetrahedron(&points) {
+        None => return Vec::new(),
+        Some(t) => t,
+    };
+
+    let normal = |f: &[usize; 3]| {
+        let n = geom::cross(
+            geom::sub(points[f[1]], points[f[0]]),
+            geom::sub(points[f[2]], points[f[0]]),
+        );
+        geom::mul(n, 1.0 / geom::length(n))
+    };
+    let outside =
+        |f: &[usize; 3], p: Point3| geom::dot(normal(f), geom::sub(p, points[f[0]])) > EPSILON;
+
+    // Every face of the tetrahedron faces away from the corner it doesn't have
+    let mut faces: Vec<[usize; 3]> = Vec::new();
+    for skip in 0..4 {
+        let mut f = [0; 3];
+        let mut corners = (0..4).filter(|&i| i != skip).map(|i| start[i]);
+        for c in f.iter_mut() {
+            *c = corners.next().unwrap();
+        }
+        if outside(&f, points[start[skip]]) {
+            f.swap(1, 2);
+        }
+        faces.push(f);
+    }
+
+    for i in rest {
+        let p = points[i];
+        let (visible, kept): (Vec<[usize; 3]>, Vec<[usize; 3]>) =
+            faces.into_iter().partition(|f| outside(f, p));
+        faces = kept;
+        if visible.is_empty() {
+            continue;
+        }
+
+        // The edges of the visible faces that aren't shared by two of them are the horizon, which
+        // gets connected to the new point
+        let edges: HashSet<(usize, usize)> = visible
+            .iter()
+            .flat_map(|f| vec![(f[0], f[1]), (f[1], f[2]), (f[2], f[0])])
+            .collect();
+        for &(a, b) in &edges {
+            if !edges.contains(&(b, a)) {
+                faces.push([a, b, i]);
+            }
+        }
+    }
+
+    faces
+        .iter()
+        .map(|f| Face {
+            points: f.iter().map(|&i| points[i]).collect(),
+            material: None,
+        })
+        .collect()
+}
+
+/// Four points that make up a tetrahedron with some volume, and the indices of the other points
+fn tetrahedron(points: &[Point3]) -> Option<([usize; 4], Vec<usize>)> {
+    if points.len() < 4 {
+        return None;
+    }
+
+    let farthest = |distance: &dyn Fn(Point3) -> f64| {
+        (0..points.len())
+            .max_by(|&a, &b| distance(points[a]).total_cmp(&distance(points[b])))
+            .unwrap()
+    };
+
+    let a = 0;
+    let b = farthest(&|p| geom::length(geom::sub(p, points[a])));
+    let ab = geom::sub(points[b], points[a]);
+    let c = farthest(&|p| geom::length(geom::cross(ab, geom::sub(p, points[a]))));
+    let n = geom::cross(ab, geom::sub(points[c], points[a]));
+    let d = farthest(&|p| geom::dot(n, geom::sub(p, points[a])).abs());
+
+    let scale = geom::length(ab);
+    if scale == 0.0
+        || geom::dot(n, geom::sub(points[d], points[a])).abs() <= EPSILON * scale.powi(3)
+    {
+        return None;
+    }
+
+    let start = [a, b, c, d];
+    let rest = (0..points.len()).filter(|i| !start.contains(i)).collect();
+    Some((start, rest))
+}
@@ -0,0 +1,179 @@
+// Maps the materials used in models to what every backend needs. A mapping file is TOML, with a
+// table for every material:
+//
+//     # Comments start with a #
+//     [wood]
+//     minecraft = "oak_planks"
+//     color = [0.64, 0.51, 0.31]  # RGB or RGBA, for OpenSCAD and OBJ
+//     mtl = "Wood"                # The name used in MTL files (default: the material's name)
+//
+// Materials without an entry are Minecraft blocks.
+
+use std::collections::HashMap;
+
+/// A colour, with every channel from 0 to 1
+pub type Rgba = (f64, f64, f64, f64);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Material {
+    /// A Minecraft block state, like `oak_stairs[facing=east]`
+    minecraft: Option<String>,
+    color: Option<Rgba>,
+    mtl: Option<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Materials(HashMap<String, Material>);
+
+/// Approximate colours of some common Minecraft blocks
+static BLOCK_COLORS: &[(&str, Rgba)] = &[
+    ("stone", (0.49, 0.49, 0.49, 1.0)),
+    ("cobblestone", (0.48, 0.48, 0.48, 1.0)),
+    ("dirt", (0.53, 0.38, 0.26, 1.0)),
+    ("grass_block", (0.37, 0.6, 0.27, 1.0)),
+    ("sand", (0.86, 0.81, 0.64, 1.0)),
+    ("oak_planks", (0.64, 0.51, 0.31, 1.0)),
+    ("spruce_planks", (0.45, 0.34, 0.21, 1.0)),
+    ("birch_planks", (0.75, 0.69, 0.48, 1.0)),
+    ("dark_oak_planks", (0.26, 0.17, 0.08, 1.0)),
+    ("bricks", (0.59, 0.38, 0.33, 1.0)),
+    ("quartz_block", (0.93, 0.9, 0.86, 1.0)),
+    ("glass", (0.75, 0.85, 0.9, 0.3)),
+];
+
+/// The name of a Minecraft block, without its namespace and block states
+fn block_name(block: &str) -> &str {
+    let name = block.split('[').next().unwrap_or(block);
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+impl Materials {
+    pub fn parse(src: &str) -> Result<Materials, String> {
+        let table: toml::Table = src.parse().map_err(|e: toml::de::Error| e.to_string())?;
+
+        let mut materials = HashMap::new();
+        for (name, entry) in table {
+            let error = |msg: String| format!("[{}]: {}", name, msg);
+            let entry = match entry {
+                toml::Value::Table(entry) => entry,
+                _ => return Err(error("Expected a table of keys".to_owned())),
+            };
+
+            let mut material = Material::default();
+            for (key, value) in entry {
+                let string = || match &value {
+                    toml::Value::String(s) => Ok(s.clone()),
+                    _ => Err(error(format!("Expected a string for {}", key))),
+                };
+                match key.as_str() {
+                    "minecraft" => material.minecraft = Some(string()?),
+                    "mtl" => material.mtl = Some(string()?),
+                    "color" => material.color = Some(parse_color(&value).map_err(error)?),
+                    _ => return Err(error(format!("Unknown key: {}", key))),
+                }
+            }
+            materials.insert(name, material);
+        }
+
+        Ok(Materials(materials))
+    }
+
+    /// The Minecraft block state of a material
+    pub fn minecraft_block<'a>(&'a self, material: &'a str) -> &'a str {
+        self.0
+            .get(material)
+            .and_then(|m| m.minecraft.as_deref())
+            .unwrap_or(material)
+    }
+
+    /// The colour of a material, if it is known
+    pub fn color(&self, material: &str) -> Option<Rgba> {
+        if let Some(color) = self.0.get(material).and_then(|m| m.color) {
+            return Some(color);
+        }
+
+        let block = block_name(self.minecraft_block(material));
+        BLOCK_COLORS
+            .iter()
+            .find(|(n, _)| *n == block)
+            .map(|(_, color)| *color)
+    }
+
+    /// The argument of OpenSCAD's `color()` for a material
+    pub fn scad_color(&self, material: &str) -> String {
+        if let Some((r, g, b, a)) = self.color(material) {
+            return format!("[{}, {}, {}, {}]", r, g, b, a);
+        }
+
+        // Coloured blocks like red_wool or light_blue_concrete use the colour's name, and
+        // anything else is given to OpenSCAD as is (which knows the SVG colour names)
+        let block = block_name(self.minecraft_block(material));
+        match ["_wool", "_concrete", "_terracotta", "_stained_glass"]
+            .iter()
+            .find_map(|suffix| block.strip_suffix(suffix))
+        {
+            Some(color) => format!("\"{}\"", color.replace('_', "")),
+            None => format!("\"{}\"", block),
+        }
+    }
+
+    /// The name of a material in MTL files, which can't contain whitespace
+    pub fn mtl_name(&self, material: &str) -> String {
+        let name = self
+            .0
+            .get(material)
+            .and_then(|m| m.mtl.as_deref())
+            .unwrap_or(material);
+        name.split_whitespace().collect::<Vec<&str>>().join("_")
+    }
+}
+
+/// Parses `[r, g, b]` or `[r, g, b, a]`
+fn parse_color(v: &toml::Value) -> Result<Rgba, String> {
+    let invalid = || format!("Invalid color: {} (expected [r, g, b] or [r, g, b, a])", v);
+
+    let channels = v
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|c| match c {
+            toml::Value::Float(c) => Some(*c),
+            toml::Value::Integer(c) => Some(*c as f64),
+            _ => None,
+        })
+        .collect::<Option<Vec<f64>>>()
+        .ok_or_else(invalid)?;
+
+    match channels.as_slice() {
+        [r, g, b] => Ok((*r, *g, *b, 1.0)),
+        [r, g, b, a] => Ok((*r, *g, *b, *a)),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_example_mapping() {
+        let materials = Materials::parse(include_str!("../../resources/materials.toml")).unwrap();
+
+        assert_eq!(
+            materials.minecraft_block("roof"),
+            "dark_oak_stairs[facing=north]"
+        );
+        assert_eq!(materials.color("window"), Some((0.75, 0.85, 0.9, 0.3)));
+        assert_eq!(materials.mtl_name("window"), "Glass");
+        assert_eq!(materials.mtl_name("wood"), "wood");
+        assert_eq!(materials.minecraft_block("stone"), "stone");
+    }
+
+    #[test]
+    fn rejects_invalid_mappings() {
+        assert!(Materials::parse("[wood]\nminecraft = oak_planks").is_err());
+        assert!(Materials::parse("[wood]\ncolor = [1, 0]").is_err());
+        assert!(Materials::parse("[wood]\ntexture = \"oak\"").is_err());
+        assert!(Materials::parse("wood = \"oak_planks\"").is_err());
+    }
+}
@@ -1,6 +1,9 @@
 use conv::prelude::*;
+use std::collections::{HashMap, HashSet};
 
 use super::eval;
+use super::geom::{self, Point3};
+use super::mesh;
 
 #[derive(Clone)]
 pub struct Block {
@@ -22,153 +25,1313 @@ impl Block {
     }
 }
 
-fn apply_scale(v: f64, scale: i32) -> isize {
-    (v * f64::from(scale)).approx().unwrap()
+/// The data version of Minecraft 1.20.1, used by the structure and schematic files (older versions
+/// load them just fine)
+pub const DATA_VERSION: i32 = 3465;
+
+/// A position or size using Minecraft's axes, where y is up
+pub type McPos = (i32, i32, i32);
+
+pub fn mc_pos(b: &Block) -> McPos {
+    (b.pos.0 as i32, b.pos.2 as i32, b.pos.1 as i32)
 }
 
-pub fn process_native(native: &eval::NativeValue, scale: i32, out: &mut Vec<Block>) {
-    let polygon_2_blocks = |p: &eval::Polygon, scale: i32| {
-        // - Make a square that fits the whole of the polygon (max and min x and y)
-        // - Loop through each and every one of these blocks and see which ones we should "keep" using
-        //      this Ray Casting Algorithm from wikipedia: https://en.wikipedia.org/wiki/Point_in_polygon#/media/File:RecursiveEvenPolygon.svg
+pub fn relative_to(p: McPos, origin: McPos) -> McPos {
+    (p.0 - origin.0, p.1 - origin.1, p.2 - origin.2)
+}
 
-        let mut out = Vec::new();
+/// The lowest corner and the size of the area covered by the blocks, using Minecraft's axes
+pub fn mc_area(blocks: &[Block]) -> (McPos, McPos) {
+    if blocks.is_empty() {
+        return ((0, 0, 0), (0, 0, 0));
+    }
 
-        let xmin = p.0.iter().map(|x| apply_scale(x.0, scale)).min().unwrap();
-        let xmax = p.0.iter().map(|x| apply_scale(x.0, scale)).max().unwrap();
-
-        let ymin = p.0.iter().map(|x| apply_scale(x.1, scale)).min().unwrap();
-        let ymax = p.0.iter().map(|x| apply_scale(x.1, scale)).max().unwrap();
-
-        for y in (ymin - 1)..(ymax + 1) {
-            // Find coordinates for lines we intersect in this polygon
-            let intersections = {
-                let mut out = Vec::new();
-
-                let mut p_idx = 0;
-                while p_idx < p.0.len() {
-                    let (p1, p2) = if p_idx == 0 {
-                        let p1 = (p.0)[p.0.len() - 1];
-                        let p2 = (p.0)[p_idx];
-                        (
-                            (apply_scale(p1.0, scale), apply_scale(p1.1, scale)),
-                            (apply_scale(p2.0, scale), apply_scale(p2.1, scale)),
-                        )
-                    } else {
-                        let p1 = (p.0)[p_idx - 1];
-                        let p2 = (p.0)[p_idx];
-                        (
-                            (apply_scale(p1.0, scale), apply_scale(p1.1, scale)),
-                            (apply_scale(p2.0, scale), apply_scale(p2.1, scale)),
-                        )
-                    };
+    let mut min = (i32::MAX, i32::MAX, i32::MAX);
+    let mut max = (i32::MIN, i32::MIN, i32::MIN);
+    for p in blocks.iter().map(mc_pos) {
+        min = (min.0.min(p.0), min.1.min(p.1), min.2.min(p.2));
+        max = (max.0.max(p.0), max.1.max(p.1), max.2.max(p.2));
+    }
+
+    (
+        min,
+        (max.0 - min.0 + 1, max.1 - min.1 + 1, max.2 - min.2 + 1),
+    )
+}
+
+fn apply_scale(v: f64, scale: i32) -> Result<isize, String> {
+    to_block(v * f64::from(scale))
+}
+
+/// Converts a (scaled) coordinate to a block position, which fails for coordinates too far out to
+/// build (or that aren't numbers at all, like after dividing by zero)
+fn to_block(v: f64) -> Result<isize, String> {
+    v.approx().map_err(|_| {
+        format!(
+            "The coordinate {:e} (in blocks) is too far out to build in Minecraft",
+            v
+        )
+    })
+}
+
+/// Finds the (sorted) x coordinates where the edges of a polygon's rings cross the horizontal
+/// line at `y`. An edge only counts when `min_y <= y < max_y`, so a vertex lying exactly on the
+/// line is counted once (by the edge going upwards from it) and horizontal edges are skipped
+/// altogether.
+fn scanline_intersections(rings: &[Vec<(f64, f64)>], y: f64) -> Vec<f64> {
+    let mut out = Vec::new();
+    for points in rings {
+        for (i, p2) in points.iter().enumerate() {
+            let p1 = points[if i == 0 { points.len() - 1 } else { i - 1 }];
+            let (low, high) = if p1.1 < p2.1 { (p1, *p2) } else { (*p2, p1) };
+
+            if low.1 <= y && y < high.1 {
+                out.push(low.0 + (y - low.1) * (high.0 - low.0) / (high.1 - low.1));
+            }
+        }
+    }
+
+    out.sort_by(|a, b| a.total_cmp(b));
+    out
+}
+
+/// Rasterizes a polygon into the (x, y) positions of the blocks it covers.
+///
+/// A block is included when its centre, `(x + 0.5, y + 0.5)` in scaled
+/// coordinates, lies inside the polygon by the even-odd rule (so rings inside
+/// of other rings are holes). Every row is intersected with the horizontal
+/// line through its block centres, computing the (fractional) x coordinate
+/// where each edge crosses it (see `scanline_intersections`).
+///
+/// Circles are rasterized from their points too, so like in OpenSCAD they get rounder with more
+/// segments.
+fn rasterize_polygon(polygon: &eval::Polygon, scale: i32) -> Result<Vec<(isize, isize)>, String> {
+    let mut out = Vec::new();
+    let rings = scale_rings(polygon, scale);
 
-                    // Make sure this line is relevant
-                    if (p2.1 <= y && y < p1.1) || (p1.1 <= y && y < p2.1) {
-                        let dx = p1.0 - p2.0;
-                        let _dy = p1.1 - p2.1;
-
-                        let ip = if dx == 0 {
-                            p1.0
-                        } else {
-                            todo!("Lines that are not parallel with the x or y axis are not supported yet.");
-                            // let k = dy / dx;
-                            // let m = p1.1 - k * p1.0;
-                            // let ip = if k == 0 { y - m } else { (y - m) / k };
-                            // println!("k={} m={} (dy={} ; dx = {})", k, m, dy, dx);
-                            // ip
-                        };
-
-                        out.push(ip);
+    let points: Vec<&(f64, f64)> = rings.iter().flatten().collect();
+    if points.len() < 3 {
+        return Ok(out);
+    }
+
+    let ymin = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+    let ymax = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+
+    let ymin = to_block(ymin.floor())?;
+    let ymax = to_block(ymax.ceil())?;
+
+    for y in ymin..ymax {
+        let intersections = scanline_intersections(&rings, y as f64 + 0.5);
+
+        // Every pair of intersections is a span inside the polygon. Keep the blocks whose
+        // centre is in [start, end).
+        for span in intersections.chunks_exact(2) {
+            let start = to_block((span[0] - 0.5).ceil())?;
+            let end = to_block((span[1] - 0.5).ceil())?;
+
+            out.extend((start..end).map(|x| (x, y)));
+        }
+    }
+
+    Ok(out)
+}
+
+/// Checks if a point (in scaled coordinates) is inside a polygon, using the same rule as
+/// `rasterize_polygon`.
+fn polygon_contains(polygon: &eval::Polygon, scale: i32, p: (f64, f64)) -> bool {
+    let crossed = scanline_intersections(&scale_rings(polygon, scale), p.1)
+        .into_iter()
+        .filter(|x| *x <= p.0)
+        .count();
+
+    crossed % 2 == 1
+}
+
+fn circle_contains(r: f64, p: (f64, f64)) -> bool {
+    p.0 * p.0 + p.1 * p.1 < r * r
+}
+
+fn scale_rings(polygon: &eval::Polygon, scale: i32) -> Vec<Vec<(f64, f64)>> {
+    let scale = f64::from(scale);
+    polygon
+        .rings()
+        .into_iter()
+        .map(|ring| ring.iter().map(|p| (p.0 * scale, p.1 * scale)).collect())
+        .collect()
+}
+
+fn is_whole_blocks(v: f64, scale: i32) -> bool {
+    let v = v * f64::from(scale);
+    v == v.round()
+}
+
+/// The layers (z positions of the blocks) an extrusion covers. Centred extrusions with an odd
+/// number of layers have the extra one on top.
+fn extrude_layers(e: &eval::Extrusion, scale: i32) -> Result<std::ops::Range<isize>, String> {
+    let n = apply_scale(e.height, scale)?;
+    let start = if e.center { -(n / 2) } else { 0 };
+    Ok(start..start + n)
+}
+
+/// Checks if a point (in unscaled coordinates) is inside an extrusion. Twisted and scaled
+/// extrusions are undone at the height of the point, which is then looked up in the polygon.
+fn extrusion_contains(
+    polygon: &eval::Polygon,
+    e: &eval::Extrusion,
+    p: geom::Point3,
+    scale: i32,
+) -> bool {
+    let s = f64::from(scale);
+    // Points too far out to build aren't in any block
+    let (layers, layer) = match (extrude_layers(e, scale), to_block((p.2 * s).floor())) {
+        (Ok(layers), Ok(layer)) => (layers, layer),
+        _ => return false,
+    };
+    if !layers.contains(&layer) {
+        return false;
+    }
+
+    let t = (p.2 * s - layers.start as f64) / (layers.end - layers.start) as f64;
+    match e.apply_inverse((p.0, p.1), t.clamp(0.0, 1.0)) {
+        None => false,
+        Some(base) => polygon_contains(polygon, scale, (base.0 * s, base.1 * s)),
+    }
+}
+
+/// The direction of the rays that points are tested with. It won't run along the edges of the
+/// triangles, and goes (almost) along the x-axis, so only the triangles around a point in y and z
+/// can be crossed.
+const RAY: Point3 = (1.0, 0.000_137, 0.000_271);
+
+/// A native value to sample, with the meshes of its hulls and Minkowski sums computed up front.
+/// They take far too long to compute for every block that is tested.
+enum Sampled<'a> {
+    /// A value that is tested directly, with its children (if it has any)
+    Native(&'a eval::NativeValue, Vec<Sampled<'a>>),
+    /// A shell, with its children and the transforms between it and the value being sampled
+    /// (outermost first). Its walls are as thick as asked for in the blocks being placed, not in
+    /// its own space, which may be scaled or rotated.
+    Shell(
+        &'a eval::NativeValue,
+        Vec<Sampled<'a>>,
+        Vec<&'a eval::Transform>,
+    ),
+    /// A hull or Minkowski sum, which is tested through its mesh
+    Mesh(MeshSolid),
+}
+
+impl<'a> Sampled<'a> {
+    fn new(native: &'a eval::NativeValue) -> Sampled<'a> {
+        Sampled::within(native, &mut Vec::new())
+    }
+
+    /// Builds the value to sample for a native value within `transforms`
+    fn within(
+        native: &'a eval::NativeValue,
+        transforms: &mut Vec<&'a eval::Transform>,
+    ) -> Sampled<'a> {
+        match native {
+            eval::NativeValue::Hull(_) | eval::NativeValue::Minkowski(_) => {
+                Sampled::Mesh(MeshSolid::new(native))
+            }
+            eval::NativeValue::Transform(t, children) => {
+                transforms.push(t);
+                let children = Sampled::all_within(children, transforms);
+                transforms.pop();
+                Sampled::Native(native, children)
+            }
+            eval::NativeValue::Shell(_, children) => Sampled::Shell(
+                native,
+                Sampled::all_within(children, transforms),
+                transforms.clone(),
+            ),
+            eval::NativeValue::Union(children)
+            | eval::NativeValue::Difference(children)
+            | eval::NativeValue::Intersection(children)
+            | eval::NativeValue::Material(_, children) => {
+                Sampled::Native(native, Sampled::all_within(children, transforms))
+            }
+            eval::NativeValue::Polygon(_)
+            | eval::NativeValue::Extrude(_, _)
+            | eval::NativeValue::RotateExtrude(_, _, _)
+            | eval::NativeValue::Cube(_, _)
+            | eval::NativeValue::Sphere(_, _)
+            | eval::NativeValue::Cylinder(_, _, _, _) => Sampled::Native(native, Vec::new()),
+        }
+    }
+
+    fn all_within(
+        natives: &'a [eval::NativeValue],
+        transforms: &mut Vec<&'a eval::Transform>,
+    ) -> Vec<Sampled<'a>> {
+        natives
+            .iter()
+            .map(|n| Sampled::within(n, transforms))
+            .collect()
+    }
+}
+
+/// The triangles of a value that can only be rasterized through its mesh (like a hull), with
+/// their bounds
+struct MeshSolid {
+    triangles: Vec<([Point3; 3], geom::Bounds)>,
+    flat: bool,
+    grid: TriangleGrid,
+}
+
+impl MeshSolid {
+    fn new(native: &eval::NativeValue) -> MeshSolid {
+        let (triangles, flat) = match mesh::from_native(native) {
+            Ok(mesh) => (
+                mesh.triangles()
+                    .into_iter()
+                    .map(|(t, _)| (t, geom::bounds_of(&t)))
+                    .collect(),
+                mesh.flat,
+            ),
+            Err(e) => {
+                eprintln!("warning: Leaving out a {}: {}", native.name(), e);
+                (Vec::new(), false)
+            }
+        };
+
+        let grid = TriangleGrid::new(&triangles);
+        MeshSolid {
+            triangles,
+            flat,
+            grid,
+        }
+    }
+
+    /// Checks if a point is inside, by counting how many triangles a ray from it crosses. Flat
+    /// meshes cover their triangles instead (in the layer of blocks at z = 0).
+    fn contains(&self, p: Point3, layer: isize) -> bool {
+        if self.flat {
+            return layer == 0
+                && self.triangles.iter().any(|(t, (min, max))| {
+                    if p.0 < min.0 || p.0 > max.0 || p.1 < min.1 || p.1 > max.1 {
+                        return false;
                     }
 
-                    p_idx += 1;
+                    let side = |a: Point3, b: Point3| {
+                        (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0) >= 0.0
+                    };
+                    let sides = [side(t[0], t[1]), side(t[1], t[2]), side(t[2], t[0])];
+                    sides.iter().all(|s| *s) || sides.iter().all(|s| !*s)
+                });
+        }
+
+        let crossed = self
+            .grid
+            .near(p)
+            .iter()
+            .map(|&i| &self.triangles[i])
+            .filter(|(t, b)| ray_reaches(b, p, RAY) && ray_crosses(t, p, RAY))
+            .count();
+        crossed % 2 == 1
+    }
+
+    fn bounds(&self, scale: i32) -> Option<geom::Bounds> {
+        let (min, max) = self
+            .triangles
+            .iter()
+            .map(|(_, b)| *b)
+            .reduce(geom::union_bounds)?;
+        Some(if self.flat {
+            (min, (max.0, max.1, 1.0 / f64::from(scale)))
+        } else {
+            (min, max)
+        })
+    }
+}
+
+/// The triangles of a mesh sorted into a grid of cells in y and z, by the points whose rays (see
+/// `RAY`) can reach them
+struct TriangleGrid {
+    min: (f64, f64),
+    cell_size: (f64, f64),
+    size: usize,
+    cells: Vec<Vec<usize>>,
+}
+
+impl TriangleGrid {
+    fn new(triangles: &[([Point3; 3], geom::Bounds)]) -> TriangleGrid {
+        let (min, max) = match triangles.iter().map(|(_, b)| *b).reduce(geom::union_bounds) {
+            Some(b) => b,
+            None => {
+                return TriangleGrid {
+                    min: (0.0, 0.0),
+                    cell_size: (1.0, 1.0),
+                    size: 0,
+                    cells: Vec::new(),
                 }
+            }
+        };
 
-                //TODO Order this list to find the number of total intersections without looking through the whole list
-                out
-            };
+        // On its way through the mesh, a ray moves this far in y and z, so it can reach triangles
+        // from up to that far below them
+        let drift = (RAY.1 * (max.0 - min.0), RAY.2 * (max.0 - min.0));
 
-            for x in (xmin - 1)..(xmax + 1) {
-                let mut total_lines_intersected = 0;
-                //TODO If intersections were ordered we would be able to break on ip > x
-                for ip in &intersections {
-                    if *ip <= x {
-                        total_lines_intersected += 1;
-                    }
+        let size = (triangles.len() as f64).sqrt().ceil() as usize;
+        let mut grid = TriangleGrid {
+            min: (min.1 - drift.0, min.2 - drift.1),
+            cell_size: (
+                (max.1 - min.1 + drift.0) / size as f64,
+                (max.2 - min.2 + drift.1) / size as f64,
+            ),
+            size,
+            cells: vec![Vec::new(); size * size],
+        };
+
+        for (i, (_, (min, max))) in triangles.iter().enumerate() {
+            let ys = grid.cell(min.1 - drift.0, 0)..=grid.cell(max.1, 0);
+            let zs = grid.cell(min.2 - drift.1, 1)..=grid.cell(max.2, 1);
+            for y in ys {
+                for z in zs.clone() {
+                    grid.cells[y * size + z].push(i);
                 }
+            }
+        }
+
+        grid
+    }
+
+    /// The cell a coordinate (y for axis 0 and z for axis 1) is in, clamped to the grid
+    fn cell(&self, v: f64, axis: usize) -> usize {
+        let (min, cell_size) = match axis {
+            0 => (self.min.0, self.cell_size.0),
+            _ => (self.min.1, self.cell_size.1),
+        };
+        let cell = ((v - min) / cell_size).floor();
+        if cell.is_nan() || cell < 0.0 {
+            0
+        } else {
+            (cell as usize).min(self.size - 1)
+        }
+    }
+
+    /// The triangles a ray from a point can reach
+    fn near(&self, p: Point3) -> &[usize] {
+        let inside =
+            |v: f64, min: f64, cell_size: f64| min <= v && v <= min + cell_size * self.size as f64;
+        if self.size == 0
+            || !inside(p.1, self.min.0, self.cell_size.0)
+            || !inside(p.2, self.min.1, self.cell_size.1)
+        {
+            return &[];
+        }
+
+        &self.cells[self.cell(p.1, 0) * self.size + self.cell(p.2, 1)]
+    }
+}
+
+/// Checks if a ray from `p` in the direction `d` (which has to be positive along every axis)
+/// reaches a box, as it has to to cross any triangle in it
+fn ray_reaches(b: &geom::Bounds, p: Point3, d: Point3) -> bool {
+    // A little extra room, for triangles that are exactly as thick as their bounds
+    let (min, max) = (
+        geom::add(b.0, (-1e-9, -1e-9, -1e-9)),
+        geom::add(b.1, (1e-9, 1e-9, 1e-9)),
+    );
+
+    let enter = ((min.0 - p.0) / d.0)
+        .max((min.1 - p.1) / d.1)
+        .max((min.2 - p.2) / d.2)
+        .max(0.0);
+    let exit = ((max.0 - p.0) / d.0)
+        .min((max.1 - p.1) / d.1)
+        .min((max.2 - p.2) / d.2);
+    enter <= exit
+}
+
+/// Checks if a ray from `p` in the direction `d` crosses a triangle (the Möller–Trumbore
+/// algorithm)
+fn ray_crosses(t: &[Point3; 3], p: Point3, d: Point3) -> bool {
+    let e1 = geom::sub(t[1], t[0]);
+    let e2 = geom::sub(t[2], t[0]);
+    let h = geom::cross(d, e2);
+    let a = geom::dot(e1, h);
+    if a.abs() < 1e-12 {
+        return false;
+    }
+
+    let s = geom::sub(p, t[0]);
+    let u = geom::dot(s, h) / a;
+    if !(0.0..=1.0).contains(&u) {
+        return false;
+    }
+
+    let q = geom::cross(s, e1);
+    let v = geom::dot(d, q) / a;
+    if v < 0.0 || u + v > 1.0 {
+        return false;
+    }
+
+    geom::dot(e2, q) / a > 0.0
+}
+
+/// The bounds (in unscaled coordinates) of the blocks a native value would produce, or `None`
+/// if it produces none.
+fn bounds(value: &Sampled, scale: i32) -> Option<geom::Bounds> {
+    let (native, children) = match value {
+        Sampled::Mesh(mesh) => return mesh.bounds(scale),
+        Sampled::Native(native, children) | Sampled::Shell(native, children, _) => {
+            (*native, children)
+        }
+    };
+
+    let polygon_bounds = |p: &eval::Polygon, z0: f64, z1: f64| {
+        // Offsetting a polygon inwards can leave nothing of it
+        if p.0.is_empty() {
+            return None;
+        }
+        let points: Vec<geom::Point3> =
+            p.0.iter()
+                .flat_map(|&(x, y)| vec![(x, y, z0), (x, y, z1)])
+                .collect();
+        Some(geom::bounds_of(&points))
+    };
+
+    match native {
+        eval::NativeValue::Polygon(p) => polygon_bounds(p, 0.0, 1.0 / f64::from(scale)),
+        eval::NativeValue::Extrude(p, e) if e.is_straight() => {
+            polygon_bounds(p, e.bottom(), e.bottom() + e.height)
+        }
+        eval::NativeValue::Extrude(p, e) => {
+            // Whichever way it's twisted, it stays within the circle around the polygon
+            let (min, max) = polygon_bounds(p, 0.0, 0.0)?;
+            let r = [min.0, min.1, max.0, max.1]
+                .iter()
+                .fold(0.0, |r: f64, v| r.max(v.abs()))
+                * std::f64::consts::SQRT_2
+                * e.top_scale.0.max(e.top_scale.1).max(1.0);
+            Some(((-r, -r, e.bottom()), (r, r, e.bottom() + e.height)))
+        }
+        eval::NativeValue::RotateExtrude(p, _, _) => {
+            // The profile's x is the radius, and its y the height
+            let (min, max) = polygon_bounds(p, 0.0, 0.0)?;
+            Some(((-max.0, -max.0, min.1), (max.0, max.0, max.1)))
+        }
+        eval::NativeValue::Cube(size, center) => {
+            let min = if *center {
+                geom::mul(*size, -0.5)
+            } else {
+                (0.0, 0.0, 0.0)
+            };
+            Some((min, geom::add(min, *size)))
+        }
+        eval::NativeValue::Sphere(r, _) => Some(((-r, -r, -r), (*r, *r, *r))),
+        eval::NativeValue::Cylinder(h, r1, r2, _) => {
+            let r = r1.max(*r2);
+            Some(((-r, -r, 0.0), (r, r, *h)))
+        }
+        eval::NativeValue::Transform(t, _) => {
+            children_bounds(children, scale).map(|b| geom::transform_bounds(t, b))
+        }
+        eval::NativeValue::Union(_)
+        | eval::NativeValue::Shell(_, _)
+        | eval::NativeValue::Material(_, _) => children_bounds(children, scale),
+        // Nothing outside of the first value is ever kept
+        eval::NativeValue::Difference(_) | eval::NativeValue::Intersection(_) => {
+            bounds(&children[0], scale)
+        }
+        eval::NativeValue::Hull(_) | eval::NativeValue::Minkowski(_) => {
+            unreachable!("hulls and Minkowski sums are sampled through their meshes")
+        }
+    }
+}
+
+fn children_bounds(children: &[Sampled], scale: i32) -> Option<geom::Bounds> {
+    children
+        .iter()
+        .filter_map(|c| bounds(c, scale))
+        .fold(None, |acc, b| match acc {
+            None => Some(b),
+            Some(acc) => Some(geom::union_bounds(acc, b)),
+        })
+}
+
+/// Checks if a point (in unscaled coordinates) is inside one of the blocks a native value would
+/// produce. This is what lets transformed values be rasterized again in their new position,
+/// instead of moving (and rounding) blocks that have already been placed.
+fn contains(value: &Sampled, p: geom::Point3, scale: i32) -> bool {
+    let s = f64::from(scale);
+    // Points too far out to build aren't in any block
+    let layer = match to_block((p.2 * s).floor()) {
+        Ok(layer) => layer,
+        Err(_) => return false,
+    };
+
+    let (native, children, transforms) = match value {
+        Sampled::Mesh(mesh) => return mesh.contains(p, layer),
+        Sampled::Native(native, children) => (*native, children, &[][..]),
+        Sampled::Shell(native, children, transforms) => (*native, children, &transforms[..]),
+    };
+
+    match native {
+        eval::NativeValue::Polygon(poly) => {
+            layer == 0 && polygon_contains(poly, scale, (p.0 * s, p.1 * s))
+        }
+        eval::NativeValue::Extrude(poly, e) => extrusion_contains(poly, e, p, scale),
+        eval::NativeValue::RotateExtrude(poly, angle, _) => {
+            // Angles go from 0 to 360 degrees when revolving counter-clockwise, and from 0 to -360
+            // when revolving clockwise
+            let mut a = p.1.atan2(p.0).to_degrees();
+            if *angle > 0.0 && a < 0.0 {
+                a += 360.0;
+            } else if *angle < 0.0 && a > 0.0 {
+                a -= 360.0;
+            }
+
+            let radius = (p.0 * p.0 + p.1 * p.1).sqrt();
+            a.abs() < angle.abs() && polygon_contains(poly, scale, (radius * s, p.2 * s))
+        }
+        eval::NativeValue::Cube(size, center) => {
+            let min = if *center {
+                geom::mul(*size, -0.5)
+            } else {
+                (0.0, 0.0, 0.0)
+            };
+            let max = geom::add(min, *size);
+            let inside = |v: f64, min: f64, max: f64| min <= v && v < max;
+            inside(p.0, min.0, max.0) && inside(p.1, min.1, max.1) && inside(p.2, min.2, max.2)
+        }
+        eval::NativeValue::Sphere(r, _) => geom::length(p) < *r,
+        eval::NativeValue::Cylinder(h, r1, r2, _) => {
+            // The radius changes linearly from the bottom to the top
+            let r = r1 + (r2 - r1) * p.2 / h;
+            0.0 <= p.2 && p.2 < *h && circle_contains(r * s, (p.0 * s, p.1 * s))
+        }
+        eval::NativeValue::Transform(t, _) => {
+            let p = t.apply_inverse(p);
+            children.iter().any(|c| contains(c, p, scale))
+        }
+        eval::NativeValue::Union(_) | eval::NativeValue::Material(_, _) => {
+            children.iter().any(|c| contains(c, p, scale))
+        }
+        eval::NativeValue::Shell(thickness, _) => {
+            // Inside of the values, with a block that isn't within reach. The blocks around are
+            // found where they're placed, and then moved back into the space of the shell.
+            let inside = |p| children.iter().any(|c| contains(c, p, scale));
+            let placed = transforms.iter().rev().fold(p, |p, t| t.apply(p));
+            let around = |(x, y, z): BlockPos| {
+                let q = (
+                    placed.0 + x as f64 / s,
+                    placed.1 + y as f64 / s,
+                    placed.2 + z as f64 / s,
+                );
+                transforms.iter().fold(q, |q, t| t.apply_inverse(q))
+            };
+
+            // Flat values only have blocks around them in their own plane, which may have been
+            // rotated out of the xy-plane
+            let flat = native.is_flat();
+            inside(p)
+                && shell_offsets(*thickness, false)
+                    .into_iter()
+                    .map(around)
+                    .filter(|q| !flat || (q.2 - p.2).abs() * s < 0.5)
+                    .any(|q| !inside(q))
+        }
+        eval::NativeValue::Difference(_) => {
+            contains(&children[0], p, scale) && !children[1..].iter().any(|c| contains(c, p, scale))
+        }
+        eval::NativeValue::Intersection(_) => children.iter().all(|c| contains(c, p, scale)),
+        eval::NativeValue::Hull(_) | eval::NativeValue::Minkowski(_) => {
+            unreachable!("hulls and Minkowski sums are sampled through their meshes")
+        }
+    }
+}
+
+/// The material of the block at a point (in unscaled coordinates), which has to be inside of the
+/// value. Like when rasterizing, the first value containing the point decides.
+fn material_at<'a>(value: &Sampled<'a>, p: geom::Point3, scale: i32) -> Option<&'a str> {
+    let (native, children) = match value {
+        Sampled::Mesh(_) => return None,
+        Sampled::Native(native, children) | Sampled::Shell(native, children, _) => {
+            (*native, children)
+        }
+    };
+
+    match native {
+        eval::NativeValue::Polygon(_)
+        | eval::NativeValue::Extrude(_, _)
+        | eval::NativeValue::RotateExtrude(_, _, _)
+        | eval::NativeValue::Cube(_, _)
+        | eval::NativeValue::Sphere(_, _)
+        | eval::NativeValue::Cylinder(_, _, _, _)
+        | eval::NativeValue::Hull(_)
+        | eval::NativeValue::Minkowski(_) => None,
+        eval::NativeValue::Transform(t, _) => {
+            let p = t.apply_inverse(p);
+            first_containing(children, p, scale).and_then(|c| material_at(c, p, scale))
+        }
+        eval::NativeValue::Union(_) | eval::NativeValue::Shell(_, _) => {
+            first_containing(children, p, scale).and_then(|c| material_at(c, p, scale))
+        }
+        eval::NativeValue::Difference(_) | eval::NativeValue::Intersection(_) => {
+            material_at(&children[0], p, scale)
+        }
+        eval::NativeValue::Material(m, _) => first_containing(children, p, scale)
+            .and_then(|c| material_at(c, p, scale))
+            .or(Some(m)),
+    }
+}
+
+fn first_containing<'s, 'a>(
+    children: &'s [Sampled<'a>],
+    p: geom::Point3,
+    scale: i32,
+) -> Option<&'s Sampled<'a>> {
+    children.iter().find(|c| contains(c, p, scale))
+}
+
+/// Rasterizes a native value by testing the centre of every block within its bounds
+fn sample_blocks(native: &eval::NativeValue, scale: i32) -> Result<Vec<Block>, String> {
+    let value = Sampled::new(native);
 
-                if total_lines_intersected % 2 == 1 {
+    let mut out = Vec::new();
+    let (min, max) = match bounds(&value, scale) {
+        None => return Ok(out),
+        Some(b) => b,
+    };
+
+    // Pad the range by a block in every direction, since positions are rounded when rasterized
+    let s = f64::from(scale);
+    let range = |min: f64, max: f64| -> Result<std::ops::Range<isize>, String> {
+        let start = to_block((min * s).floor())?;
+        let end = to_block((max * s).ceil())?;
+        Ok((start - 1)..(end + 1))
+    };
+
+    let (xs, ys, zs) = (
+        range(min.0, max.0)?,
+        range(min.1, max.1)?,
+        range(min.2, max.2)?,
+    );
+    for z in zs {
+        for y in ys.clone() {
+            for x in xs.clone() {
+                let centre = (
+                    (x as f64 + 0.5) / s,
+                    (y as f64 + 0.5) / s,
+                    (z as f64 + 0.5) / s,
+                );
+
+                if contains(&value, centre, scale) {
                     out.push(Block {
-                        pos: (
-                            x + apply_scale((p.2).0, scale),
-                            y + apply_scale((p.2).1, scale),
-                            0,
-                        ),
-                        texture: None,
-                    })
+                        pos: (x, y, z),
+                        texture: material_at(&value, centre, scale).map(str::to_owned),
+                    });
                 }
             }
         }
+    }
 
-        out
+    Ok(out)
+}
+
+pub fn process_native(
+    native: &eval::NativeValue,
+    scale: i32,
+    out: &mut Vec<Block>,
+) -> Result<(), String> {
+    let polygon_2_blocks = |p: &eval::Polygon, scale: i32| -> Result<Vec<Block>, String> {
+        Ok(rasterize_polygon(p, scale)?
+            .into_iter()
+            .map(|(x, y)| Block {
+                pos: (x, y, 0),
+                texture: None,
+            })
+            .collect())
     };
 
     match native {
-        eval::NativeValue::Polygon(p) => out.extend(polygon_2_blocks(p, scale)),
-        eval::NativeValue::Extrude(p, h) => {
-            let polygon = polygon_2_blocks(p, scale);
-
-            let height: isize = (h * f64::from(scale)).approx().unwrap();
-            for i in 0..height {
-                out.extend(
-                    polygon
-                        .iter()
-                        .map(|b| b.translated(0, 0, i + apply_scale((p.2).2, scale))),
-                );
+        eval::NativeValue::Polygon(p) => out.extend(polygon_2_blocks(p, scale)?),
+        eval::NativeValue::Extrude(p, e) if e.is_straight() => {
+            let polygon = polygon_2_blocks(p, scale)?;
+
+            for i in extrude_layers(e, scale)? {
+                out.extend(polygon.iter().map(|b| b.translated(0, 0, i)));
+            }
+        }
+        eval::NativeValue::Transform(eval::Transform::Translate(o), children)
+            if is_whole_blocks(o.0, scale)
+                && is_whole_blocks(o.1, scale)
+                && is_whole_blocks(o.2, scale) =>
+        {
+            // Moving by whole blocks doesn't need any rounding, so the blocks can be moved as is
+            let offset = (
+                apply_scale(o.0, scale)?,
+                apply_scale(o.1, scale)?,
+                apply_scale(o.2, scale)?,
+            );
+            for b in children_blocks(children, scale)?.into_iter().flatten() {
+                out.push(b.translated(offset.0, offset.1, offset.2));
             }
         }
+        // Solids and transformed values are voxelized by testing every block
+        eval::NativeValue::Extrude(_, _)
+        | eval::NativeValue::RotateExtrude(_, _, _)
+        | eval::NativeValue::Cube(_, _)
+        | eval::NativeValue::Sphere(_, _)
+        | eval::NativeValue::Cylinder(_, _, _, _)
+        | eval::NativeValue::Hull(_)
+        | eval::NativeValue::Minkowski(_)
+        | eval::NativeValue::Transform(_, _) => out.extend(sample_blocks(native, scale)?),
+        eval::NativeValue::Union(children) => {
+            let mut seen = HashSet::new();
+            for b in children_blocks(children, scale)?.into_iter().flatten() {
+                if seen.insert(b.pos) {
+                    out.push(b);
+                }
+            }
+        }
+        eval::NativeValue::Shell(thickness, children) => {
+            let mut seen = HashSet::new();
+            let blocks: Vec<Block> = children_blocks(children, scale)?
+                .into_iter()
+                .flatten()
+                .filter(|b| seen.insert(b.pos))
+                .collect();
+
+            let offsets = shell_offsets(*thickness, native.is_flat());
+            out.extend(blocks.into_iter().filter(|b| {
+                offsets
+                    .iter()
+                    .any(|&(x, y, z)| !seen.contains(&(b.pos.0 + x, b.pos.1 + y, b.pos.2 + z)))
+            }));
+        }
+        eval::NativeValue::Material(m, children) => {
+            for mut b in children_blocks(children, scale)?.into_iter().flatten() {
+                if b.texture.is_none() {
+                    b.texture = Some(m.clone());
+                }
+                out.push(b);
+            }
+        }
+        eval::NativeValue::Difference(children) => {
+            let mut children = children_blocks(children, scale)?.into_iter();
+            let first = children.next().unwrap_or_default();
+            let removed: HashSet<_> = children.flatten().map(|b| b.pos).collect();
+
+            out.extend(first.into_iter().filter(|b| !removed.contains(&b.pos)));
+        }
+        eval::NativeValue::Intersection(children) => {
+            let mut children = children_blocks(children, scale)?.into_iter();
+            let first = children.next().unwrap_or_default();
+            let rest: Vec<HashSet<_>> = children
+                .map(|c| c.into_iter().map(|b| b.pos).collect())
+                .collect();
+
+            out.extend(
+                first
+                    .into_iter()
+                    .filter(|b| rest.iter().all(|c| c.contains(&b.pos))),
+            );
+        }
     }
+
+    Ok(())
 }
 
-pub fn blocks_to_cmds(blocks: &Vec<Block>, origin: Option<(isize, isize, isize)>) -> Vec<String> {
-    let origin = if let Some(o) = origin { o } else { (0, 0, 0) };
+/// The blocks within `thickness` steps of a block, stepping to one of the blocks next to it (not
+/// diagonally) every time. A block of a shell has one of these outside of the values, which makes
+/// the shell as thick as that everywhere, with no gaps to get through. Flat values only have
+/// blocks next to them in the plane.
+fn shell_offsets(thickness: usize, flat: bool) -> Vec<BlockPos> {
+    let t = thickness as isize;
+    let depth = if flat { 0 } else { t };
 
-    println!("Blocks: {}", blocks.len());
-    blocks
-        .iter()
-        .map(|b| {
-            format!(
-                "/setblock {} {} {} {} replace",
-                b.pos.0 + origin.0,
-                b.pos.2 + origin.2,
-                b.pos.1 + origin.1,
-                if b.texture == None {
-                    "birch_planks"
-                } else {
-                    todo!("Add texture")
+    let mut out = Vec::new();
+    for z in -depth..=depth {
+        for y in -t..=t {
+            for x in -t..=t {
+                let steps = x.abs() + y.abs() + z.abs();
+                if steps > 0 && steps <= t {
+                    out.push((x, y, z));
                 }
-            )
+            }
+        }
+    }
+    out
+}
+
+/// Rasterizes every child on its own, so their blocks can be combined as sets
+fn children_blocks(children: &[eval::NativeValue], scale: i32) -> Result<Vec<Vec<Block>>, String> {
+    children
+        .iter()
+        .map(|c| {
+            let mut blocks = Vec::new();
+            process_native(c, scale, &mut blocks)?;
+            Ok(blocks)
         })
         .collect()
 }
 
-pub fn blocks_to_destroys(
-    blocks: &Vec<Block>,
+/// Minecraft refuses to fill more blocks than this with a single command
+const FILL_LIMIT: isize = 32768;
+
+type BlockPos = (isize, isize, isize);
+
+/// An inclusive box of blocks, as its lowest and highest corner
+type BlockBox = (BlockPos, BlockPos);
+
+/// Covers the positions with as few boxes as it (greedily) can, by growing every box along x, then
+/// y and then z for as long as all of the positions it grows into are left to cover
+fn merge_boxes(positions: &HashSet<BlockPos>) -> Vec<BlockBox> {
+    let mut sorted: Vec<_> = positions.iter().copied().collect();
+    sorted.sort_by_key(|p| (p.2, p.1, p.0));
+
+    let mut left = positions.clone();
+    let mut out = Vec::new();
+
+    for start in sorted {
+        if !left.contains(&start) {
+            continue;
+        }
+
+        let row_fits =
+            |y: isize, z: isize, x_end: isize| (start.0..=x_end).all(|x| left.contains(&(x, y, z)));
+
+        let mut end = start;
+        while end.0 + 1 - start.0 < FILL_LIMIT && left.contains(&(end.0 + 1, start.1, start.2)) {
+            end.0 += 1;
+        }
+
+        let width = end.0 - start.0 + 1;
+        while width * (end.1 + 2 - start.1) <= FILL_LIMIT && row_fits(end.1 + 1, start.2, end.0) {
+            end.1 += 1;
+        }
+
+        let height = end.1 - start.1 + 1;
+        while width * height * (end.2 + 2 - start.2) <= FILL_LIMIT
+            && (start.1..=end.1).all(|y| row_fits(y, end.2 + 1, end.0))
+        {
+            end.2 += 1;
+        }
+
+        for x in start.0..=end.0 {
+            for y in start.1..=end.1 {
+                for z in start.2..=end.2 {
+                    left.remove(&(x, y, z));
+                }
+            }
+        }
+        out.push((start, end));
+    }
+
+    out
+}
+
+fn box_to_cmd(b: BlockBox, origin: (isize, isize, isize), block: &str) -> String {
+    let (from, to) = b;
+    if from == to {
+        format!(
+            "/setblock {} {} {} {} replace",
+            from.0 + origin.0,
+            from.2 + origin.2,
+            from.1 + origin.1,
+            block
+        )
+    } else {
+        format!(
+            "/fill {} {} {} {} {} {} {} replace",
+            from.0 + origin.0,
+            from.2 + origin.2,
+            from.1 + origin.1,
+            to.0 + origin.0,
+            to.2 + origin.2,
+            to.1 + origin.1,
+            block
+        )
+    }
+}
+
+pub fn blocks_to_cmds(
+    blocks: &[Block],
     origin: Option<(isize, isize, isize)>,
+    default_texture: &str,
 ) -> Vec<String> {
-    let origin = if let Some(o) = origin { o } else { (0, 0, 0) };
+    let origin = origin.unwrap_or_default();
+
+    eprintln!("Blocks: {}", blocks.len());
+
+    // Later blocks replace earlier ones at the same position, like the commands would
+    let mut textures = HashMap::new();
+    for b in blocks {
+        textures.insert(b.pos, b.texture.as_deref().unwrap_or(default_texture));
+    }
+
+    // Group the positions by texture, keeping the order the textures first appeared in
+    let mut groups: Vec<(&str, HashSet<BlockPos>)> = Vec::new();
+    for b in blocks {
+        let texture = textures[&b.pos];
+        match groups.iter_mut().find(|(t, _)| *t == texture) {
+            Some((_, positions)) => {
+                positions.insert(b.pos);
+            }
+            None => groups.push((texture, vec![b.pos].into_iter().collect())),
+        }
+    }
 
-    println!("Blocks (destroy): {}", blocks.len());
-    blocks
+    groups
         .iter()
-        .map(|b| {
-            format!(
-                "/setblock {} {} {} air replace",
-                b.pos.0 + origin.0,
-                b.pos.2 + origin.2,
-                b.pos.1 + origin.1
-            )
+        .flat_map(|(texture, positions)| {
+            merge_boxes(positions)
+                .into_iter()
+                .map(move |b| box_to_cmd(b, origin, texture))
         })
         .collect()
 }
+
+pub fn blocks_to_destroys(blocks: &[Block], origin: Option<(isize, isize, isize)>) -> Vec<String> {
+    let origin = origin.unwrap_or_default();
+
+    eprintln!("Blocks (destroy): {}", blocks.len());
+    let positions = blocks.iter().map(|b| b.pos).collect();
+    merge_boxes(&positions)
+        .into_iter()
+        .map(|b| box_to_cmd(b, origin, "air"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rasterized(points: &[(f64, f64)]) -> Vec<(isize, isize)> {
+        let mut blocks = rasterize_polygon(&eval::Polygon(points.to_vec(), None, None), 1).unwrap();
+        blocks.sort();
+        blocks
+    }
+
+    fn sorted(mut blocks: Vec<(isize, isize)>) -> Vec<(isize, isize)> {
+        blocks.sort();
+        blocks
+    }
+
+    #[test]
+    fn rasterizes_a_triangle() {
+        // The centres on the diagonal edge (where x + y = 3) are left out
+        assert_eq!(
+            rasterized(&[(0.0, 0.0), (4.0, 0.0), (0.0, 4.0)]),
+            sorted(vec![(0, 0), (1, 0), (2, 0), (0, 1), (1, 1), (0, 2)])
+        );
+    }
+
+    #[test]
+    fn rasterizes_a_hexagon() {
+        let hexagon = [
+            (1.0, 0.0),
+            (3.0, 0.0),
+            (4.0, 2.0),
+            (3.0, 4.0),
+            (1.0, 4.0),
+            (0.0, 2.0),
+        ];
+        let mut expected = vec![(1, 0), (2, 0), (1, 3), (2, 3)];
+        for y in 1..3 {
+            expected.extend((0..4).map(|x| (x, y)));
+        }
+
+        assert_eq!(rasterized(&hexagon), sorted(expected));
+    }
+
+    #[test]
+    fn rasterizes_vertices_on_a_scanline() {
+        // Both edges go up from the lowest corner, which is on the first row's scanline, so that
+        // row is empty rather than filled from the corner onwards
+        let diamond = [(2.0, 0.5), (3.5, 2.0), (2.0, 3.5), (0.5, 2.0)];
+        assert_eq!(
+            rasterized(&diamond),
+            sorted(vec![(1, 1), (2, 1), (1, 2), (2, 2)])
+        );
+
+        // The edges go on past the corner at (-1, 1.5), which only counts once
+        let pentagon = [(0.0, 0.0), (4.0, 0.0), (4.0, 4.0), (0.0, 4.0), (-1.0, 1.5)];
+        let mut expected = Vec::new();
+        for (y, start) in [(0, 0), (1, -1), (2, -1), (3, 0)] {
+            expected.extend((start..4).map(|x| (x, y)));
+        }
+        assert_eq!(rasterized(&pentagon), sorted(expected));
+    }
+
+    #[test]
+    fn rasterizes_circles_from_their_segments() {
+        let square = eval::Polygon(
+            eval::circle_points(4, 2.0),
+            None,
+            Some(eval::Shape::Circle(2.0, 4)),
+        );
+        let blocks = sorted(rasterize_polygon(&square, 1).unwrap());
+        assert_eq!(blocks, rasterized(&eval::circle_points(4, 2.0)));
+
+        // A true circle would also cover the four blocks at the middle of each side
+        assert_eq!(blocks.len(), 8);
+        assert!(!polygon_contains(&square, 1, (1.5, 0.5)));
+    }
+
+    fn square(size: f64) -> eval::NativeValue {
+        eval::NativeValue::Polygon(eval::Polygon(
+            vec![(0.0, 0.0), (size, 0.0), (size, size), (0.0, size)],
+            None,
+            None,
+        ))
+    }
+
+    fn translated(offset: Point3, native: eval::NativeValue) -> eval::NativeValue {
+        eval::NativeValue::Transform(eval::Transform::Translate(offset), vec![native])
+    }
+
+    fn blocks(native: eval::NativeValue, scale: i32) -> Vec<BlockPos> {
+        let mut out = Vec::new();
+        process_native(&native, scale, &mut out).unwrap();
+        let mut positions: Vec<BlockPos> = out.into_iter().map(|b| b.pos).collect();
+        positions.sort();
+        positions
+    }
+
+    /// The blocks of a box from `min` up to (but not including) `max`
+    fn box_blocks(min: BlockPos, max: BlockPos) -> Vec<BlockPos> {
+        let mut out = Vec::new();
+        for x in min.0..max.0 {
+            for y in min.1..max.1 {
+                for z in min.2..max.2 {
+                    out.push((x, y, z));
+                }
+            }
+        }
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn rasterizes_hulls() {
+        // Two squares side by side make a rectangle, and the area is the same at every scale
+        let hull =
+            || eval::NativeValue::Hull(vec![square(2.0), translated((4.0, 0.0, 0.0), square(2.0))]);
+        assert_eq!(blocks(hull(), 1), box_blocks((0, 0, 0), (6, 2, 1)));
+        assert_eq!(blocks(hull(), 2).len(), 12 * 4);
+
+        let hull = eval::NativeValue::Hull(vec![
+            eval::NativeValue::Cube((2.0, 2.0, 2.0), false),
+            translated(
+                (0.0, 0.0, 3.0),
+                eval::NativeValue::Cube((2.0, 2.0, 2.0), false),
+            ),
+        ]);
+        assert_eq!(blocks(hull, 1), box_blocks((0, 0, 0), (2, 2, 5)));
+    }
+
+    #[test]
+    fn rasterizes_minkowski_sums() {
+        // Adding a square of 2 around the origin grows a square of 2 by 1 on every side
+        let centred = translated((-1.0, -1.0, 0.0), square(2.0));
+        let sum = eval::NativeValue::Minkowski(vec![square(2.0), centred]);
+        assert_eq!(blocks(sum, 1), box_blocks((-1, -1, 0), (3, 3, 1)));
+
+        let sum = eval::NativeValue::Minkowski(vec![
+            eval::NativeValue::Cube((2.0, 2.0, 2.0), false),
+            eval::NativeValue::Cube((2.0, 2.0, 2.0), true),
+        ]);
+        assert_eq!(blocks(sum, 2), box_blocks((-2, -2, -2), (6, 6, 6)));
+    }
+
+    /// The blocks of `all` that aren't in `removed`
+    fn without(all: Vec<BlockPos>, removed: Vec<BlockPos>) -> Vec<BlockPos> {
+        all.into_iter().filter(|p| !removed.contains(p)).collect()
+    }
+
+    fn cube(size: f64) -> eval::NativeValue {
+        eval::NativeValue::Cube((size, size, size), false)
+    }
+
+    #[test]
+    fn keeps_only_the_walls_of_shells() {
+        // A cube of 4 only loses the 2 by 2 by 2 blocks in its middle
+        let shell = eval::NativeValue::Shell(1, vec![cube(4.0)]);
+        let walls = blocks(shell, 1);
+        assert_eq!(walls.len(), 64 - 8);
+        assert_eq!(
+            walls,
+            without(
+                box_blocks((0, 0, 0), (4, 4, 4)),
+                box_blocks((1, 1, 1), (3, 3, 3))
+            )
+        );
+
+        // Walls of 2 leave the same hole in a cube of 6
+        let shell = eval::NativeValue::Shell(2, vec![cube(6.0)]);
+        let walls = blocks(shell, 1);
+        assert_eq!(walls.len(), 216 - 8);
+        assert_eq!(
+            walls,
+            without(
+                box_blocks((0, 0, 0), (6, 6, 6)),
+                box_blocks((2, 2, 2), (4, 4, 4))
+            )
+        );
+    }
+
+    #[test]
+    fn keeps_only_the_outline_of_flat_shells() {
+        // Without the blocks above and below counting as outside, which would keep every block
+        let shell = eval::NativeValue::Shell(1, vec![square(4.0)]);
+        let walls = blocks(shell, 1);
+        assert_eq!(walls.len(), 16 - 4);
+        assert_eq!(
+            walls,
+            without(
+                box_blocks((0, 0, 0), (4, 4, 1)),
+                box_blocks((1, 1, 0), (3, 3, 1))
+            )
+        );
+    }
+
+    #[test]
+    fn keeps_walls_of_shells_under_transforms() {
+        // Moved by half a block, which is sampled rather than moved block by block
+        let shell = translated(
+            (0.5, 0.5, 0.5),
+            eval::NativeValue::Shell(1, vec![cube(4.0)]),
+        );
+        let walls = blocks(shell, 1);
+        assert_eq!(walls.len(), 64 - 8);
+        assert_eq!(
+            walls,
+            without(
+                box_blocks((0, 0, 0), (4, 4, 4)),
+                box_blocks((1, 1, 1), (3, 3, 3))
+            )
+        );
+
+        // Scaling the shell doesn't scale its walls, which are still as thick as asked for
+        let shell = eval::NativeValue::Transform(
+            eval::Transform::Scale((2.0, 2.0, 2.0)),
+            vec![eval::NativeValue::Shell(1, vec![cube(3.0)])],
+        );
+        let walls = blocks(shell, 1);
+        assert_eq!(walls.len(), 216 - 64);
+        assert_eq!(
+            walls,
+            without(
+                box_blocks((0, 0, 0), (6, 6, 6)),
+                box_blocks((1, 1, 1), (5, 5, 5))
+            )
+        );
+
+        // A flat shell stood up on its side is still only its outline
+        let shell = eval::NativeValue::Transform(
+            eval::Transform::Rotate((90.0, 0.0, 0.0)),
+            vec![eval::NativeValue::Shell(1, vec![square(4.0)])],
+        );
+        assert_eq!(
+            blocks(shell, 1),
+            without(
+                box_blocks((0, -1, 0), (4, 0, 4)),
+                box_blocks((1, -1, 1), (3, 0, 3))
+            )
+        );
+    }
+
+    #[test]
+    fn finds_every_crossed_triangle_through_the_grid() {
+        let solid = MeshSolid::new(&eval::NativeValue::Minkowski(vec![
+            eval::NativeValue::Cube((2.0, 1.0, 3.0), false),
+            eval::NativeValue::Sphere(1.0, 10),
+        ]));
+
+        for i in 0..1000 {
+            let p = (
+                (i % 10) as f64 * 0.53 - 1.7,
+                (i / 10 % 10) as f64 * 0.41 - 1.3,
+                (i / 100) as f64 * 0.59 - 1.6,
+            );
+            let crossed = |triangles: Vec<&[Point3; 3]>| {
+                triangles
+                    .into_iter()
+                    .filter(|t| ray_crosses(t, p, RAY))
+                    .count()
+            };
+            assert_eq!(
+                crossed(
+                    solid
+                        .grid
+                        .near(p)
+                        .iter()
+                        .map(|&i| &solid.triangles[i].0)
+                        .collect()
+                ),
+                crossed(solid.triangles.iter().map(|(t, _)| t).collect()),
+                "at {:?}",
+                p
+            );
+        }
+    }
+
+    /// The positions the boxes cover, checking that none of them overlap
+    fn expanded(boxes: &[BlockBox]) -> HashSet<BlockPos> {
+        let mut positions = HashSet::new();
+        for &(from, to) in boxes {
+            for x in from.0..=to.0 {
+                for y in from.1..=to.1 {
+                    for z in from.2..=to.2 {
+                        assert!(
+                            positions.insert((x, y, z)),
+                            "boxes overlap at {:?}",
+                            (x, y, z)
+                        );
+                    }
+                }
+            }
+        }
+        positions
+    }
+
+    #[test]
+    fn merges_an_irregular_set_of_blocks() {
+        let mut positions = HashSet::new();
+        for x in 0..9 {
+            for y in 0..7 {
+                for z in 0..5 {
+                    if (x * 7 + y * 13 + z * 5) % 4 != 0 && x + y > z {
+                        positions.insert((x, y, z));
+                    }
+                }
+            }
+        }
+        // A column off on its own
+        positions.extend((-3..8).map(|z| (-5, 2, z)));
+
+        let boxes = merge_boxes(&positions);
+        assert!(boxes.len() < positions.len());
+        assert_eq!(expanded(&boxes), positions);
+    }
+
+    #[test]
+    fn splits_boxes_larger_than_the_fill_limit() {
+        let mut positions = HashSet::new();
+        for x in 0..50 {
+            for y in 0..40 {
+                for z in 0..30 {
+                    positions.insert((x, y, z));
+                }
+            }
+        }
+        assert!(positions.len() as isize > FILL_LIMIT);
+
+        let boxes = merge_boxes(&positions);
+        for (from, to) in &boxes {
+            let size = (to.0 - from.0 + 1) * (to.1 - from.1 + 1) * (to.2 - from.2 + 1);
+            assert!(
+                size <= FILL_LIMIT,
+                "{:?} to {:?} has {} blocks",
+                from,
+                to,
+                size
+            );
+        }
+        assert_eq!(expanded(&boxes), positions);
+    }
+}
@@ -0,0 +1,709 @@
+use super::csg;
+use super::eval;
+use super::eval::plane::{self, Point2};
+use super::geom::{self, Point3};
+use super::hull;
+
+/// A convex, planar face. The points are counter-clockwise when seen from outside of the mesh.
+#[derive(Clone, Debug)]
+pub struct Face {
+    pub points: Vec<Point3>,
+    /// The material set furthest in, if any
+    pub material: Option<String>,
+}
+
+impl Face {
+    fn new(points: Vec<Point3>) -> Face {
+        Face {
+            points,
+            material: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub faces: Vec<Face>,
+    /// Flat meshes (from 2D values like polygons) don't enclose any volume
+    pub flat: bool,
+}
+
+impl Mesh {
+    /// Splits every face into triangles. Points are welded together and added to the edges they
+    /// lie on (the boolean operations leave those behind), so that the triangles of a solid are
+    /// watertight.
+    pub fn triangles(&self) -> Vec<([Point3; 3], Option<&str>)> {
+        let faces: Vec<Vec<Point3>> = self
+            .faces
+            .iter()
+            .map(|f| f.points.iter().map(|&p| snap(p)).collect())
+            .collect();
+
+        // Sorted by x, so that we only have to look at the points near an edge
+        let mut points: Vec<Point3> = faces.iter().flatten().copied().collect();
+        points.sort_by(|a, b| geom::cmp_points(*a, *b));
+        points.dedup();
+
+        let mut out = Vec::new();
+        for (f, original) in faces.iter().zip(&self.faces) {
+            let material = original.material.as_deref();
+            let mut face = Vec::new();
+            for (i, &a) in f.iter().enumerate() {
+                face.push(a);
+                face.extend(points_on_edge(&points, a, f[(i + 1) % f.len()]));
+            }
+
+            if face.len() == f.len() {
+                for i in 1..face.len().saturating_sub(1) {
+                    out.push(([face[0], face[i], face[i + 1]], material));
+                }
+            } else {
+                // Fanning out from a corner would give triangles without area when it has
+                // points added next to it, so fan out from the middle of the (convex) face
+                let centre = geom::mul(
+                    face.iter()
+                        .fold((0.0, 0.0, 0.0), |acc, &p| geom::add(acc, p)),
+                    1.0 / face.len() as f64,
+                );
+                for (i, &a) in face.iter().enumerate() {
+                    out.push(([centre, a, face[(i + 1) % face.len()]], material));
+                }
+            }
+        }
+
+        out
+    }
+
+    fn transformed(mut self, transform: &eval::Transform) -> Mesh {
+        // Mirroring turns the faces inside out, so flip them back
+        let mirrors = match transform {
+            eval::Transform::Scale(f) => f.0 * f.1 * f.2 < 0.0,
+            _ => false,
+        };
+
+        for f in &mut self.faces {
+            for p in f.points.iter_mut() {
+                *p = transform.apply(*p);
+            }
+            if mirrors {
+                f.points.reverse();
+            }
+        }
+
+        self
+    }
+}
+
+/// Rounds away the tiny errors left by the boolean operations, so that equal points compare equal
+fn snap(p: Point3) -> Point3 {
+    let round = |v: f64| (v * 1e9).round() / 1e9 + 0.0;
+    (round(p.0), round(p.1), round(p.2))
+}
+
+/// The points (from a list sorted by x) that lie on the edge between `a` and `b`, in order
+fn points_on_edge(points: &[Point3], a: Point3, b: Point3) -> Vec<Point3> {
+    let ab = geom::sub(b, a);
+    let len2 = geom::dot(ab, ab);
+    if len2 == 0.0 {
+        return Vec::new();
+    }
+
+    let (min_x, max_x) = (a.0.min(b.0), a.0.max(b.0));
+    let start = points.partition_point(|p| p.0 < min_x - 1e-6);
+
+    let mut out: Vec<(f64, Point3)> = points[start..]
+        .iter()
+        .take_while(|p| p.0 <= max_x + 1e-6)
+        .filter_map(|&p| {
+            let t = geom::dot(geom::sub(p, a), ab) / len2;
+            let off = geom::sub(p, geom::lerp(a, b, t));
+            if t > 1e-9 && t < 1.0 - 1e-9 && geom::dot(off, off) < 1e-12 {
+                Some((t, p))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    out.sort_by(|x, y| x.0.total_cmp(&y.0));
+    out.into_iter().map(|(_, p)| p).collect()
+}
+
+/// Splits a simple (possibly concave) polygon into triangles, by ear clipping. The triangles are
+/// indices into `points`, and are always counter-clockwise.
+fn triangulate(points: &[(f64, f64)]) -> Vec<[usize; 3]> {
+    let mut idx: Vec<usize> = (0..points.len()).collect();
+    if plane::area(points) < 0.0 {
+        idx.reverse();
+    }
+
+    let cross = |a: usize, b: usize, c: usize| {
+        let (a, b, c) = (points[a], points[b], points[c]);
+        (b.0 - a.0) * (c.1 - b.1) - (b.1 - a.1) * (c.0 - b.0)
+    };
+
+    // A point is inside a (counter-clockwise) triangle if it is left of all of its edges
+    let inside = |p: (f64, f64), a: usize, b: usize, c: usize| {
+        let (a, b, c) = (points[a], points[b], points[c]);
+        let left = |u: (f64, f64), v: (f64, f64)| {
+            (v.0 - u.0) * (p.1 - u.1) - (v.1 - u.1) * (p.0 - u.0) >= 0.0
+        };
+        p != a && p != b && p != c && left(a, b) && left(b, c) && left(c, a)
+    };
+
+    let mut out = Vec::new();
+    while idx.len() >= 3 {
+        let n = idx.len();
+        let corner = |i: usize| (idx[(i + n - 1) % n], idx[i], idx[(i + 1) % n]);
+
+        let is_ear = |i: usize| {
+            let (a, b, c) = corner(i);
+            cross(a, b, c) > 0.0 && !idx.iter().any(|&j| inside(points[j], a, b, c))
+        };
+
+        // Self-intersecting or degenerate polygons may not have any ears left, clip a vertex
+        // anyway so that we always finish
+        let i = (0..n)
+            .find(|&i| is_ear(i))
+            .or_else(|| {
+                (0..n).find(|&i| {
+                    let (a, b, c) = corner(i);
+                    cross(a, b, c) >= 0.0
+                })
+            })
+            .unwrap_or(0);
+
+        let (a, b, c) = corner(i);
+        if cross(a, b, c) > 0.0 {
+            out.push([a, b, c]);
+        }
+        idx.remove(i);
+    }
+
+    out
+}
+
+/// Checks if the segments `a`-`b` and `c`-`d` cross, not counting touching ends
+fn segments_cross(a: Point2, b: Point2, c: Point2, d: Point2) -> bool {
+    let orient =
+        |p: Point2, q: Point2, r: Point2| (q.0 - p.0) * (r.1 - p.1) - (q.1 - p.1) * (r.0 - p.0);
+    if a == c || a == d || b == c || b == d {
+        return false;
+    }
+
+    let (d1, d2) = (orient(a, b, c), orient(a, b, d));
+    let (d3, d4) = (orient(c, d, a), orient(c, d, b));
+    d1 * d2 < 0.0 && d3 * d4 < 0.0
+}
+
+/// Joins the holes to their outline, with a bridge (an edge going there and back) from every hole
+/// to a corner it can see. The result is a single ring that can be split into triangles.
+fn bridge_holes(outline: Vec<Point2>, mut holes: Vec<Vec<Point2>>) -> Vec<Point2> {
+    let all_rings: Vec<Vec<Point2>> = std::iter::once(outline.clone())
+        .chain(holes.iter().cloned())
+        .collect();
+    let is_filled = |p: Point2| {
+        all_rings
+            .iter()
+            .filter(|r| plane::ring_contains(r, p))
+            .count()
+            % 2
+            == 1
+    };
+
+    // Going from right to left makes bridges cross less
+    let max_x = |r: &Vec<Point2>| r.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+    holes.sort_by(|a, b| max_x(b).total_cmp(&max_x(a)));
+
+    let mut merged = outline;
+    for (h, hole) in holes.iter().enumerate() {
+        let (start, &from) = hole
+            .iter()
+            .enumerate()
+            .max_by(|a, b| (a.1).0.total_cmp(&(b.1).0))
+            .unwrap();
+
+        let edges_of = |ring: &[Point2]| {
+            (0..ring.len())
+                .map(|i| (ring[i], ring[(i + 1) % ring.len()]))
+                .collect::<Vec<_>>()
+        };
+        let mut edges = edges_of(&merged);
+        for other in &holes[h..] {
+            edges.extend(edges_of(other));
+        }
+
+        let visible = |to: Point2| {
+            let middle = ((from.0 + to.0) / 2.0, (from.1 + to.1) / 2.0);
+            !edges.iter().any(|&(c, d)| segments_cross(from, to, c, d)) && is_filled(middle)
+        };
+        let distance = |p: Point2| (p.0 - from.0).powi(2) + (p.1 - from.1).powi(2);
+
+        // If nothing is visible (which only happens for invalid polygons) use the closest corner
+        let target = (0..merged.len())
+            .filter(|&i| visible(merged[i]))
+            .min_by(|&a, &b| distance(merged[a]).total_cmp(&distance(merged[b])))
+            .or_else(|| {
+                (0..merged.len())
+                    .min_by(|&a, &b| distance(merged[a]).total_cmp(&distance(merged[b])))
+            })
+            .unwrap();
+
+        let mut bridged = merged[..=target].to_vec();
+        bridged.extend(hole[start..].iter().chain(&hole[..start]));
+        bridged.push(from);
+        bridged.extend(&merged[target..]);
+        merged = bridged;
+    }
+
+    merged
+}
+
+/// Splits a polygon (which may be concave and have holes) into counter-clockwise triangles
+fn triangulate_polygon(polygon: &eval::Polygon) -> Vec<[Point2; 3]> {
+    let rings = plane::oriented_rings(polygon);
+
+    let mut out = Vec::new();
+    for (outline, depth) in rings.iter().filter(|(_, d)| d % 2 == 0) {
+        // The holes of an outline are the rings right inside of it
+        let holes = rings
+            .iter()
+            .filter(|(hole, d)| *d == depth + 1 && plane::ring_contains(outline, hole[0]))
+            .map(|(hole, _)| hole.clone())
+            .collect();
+
+        let points = bridge_holes(outline.clone(), holes);
+        out.extend(
+            triangulate(&points)
+                .into_iter()
+                .map(|t| [points[t[0]], points[t[1]], points[t[2]]]),
+        );
+    }
+
+    out
+}
+
+/// The polygon as faces in the xy-plane, facing upwards
+fn polygon_faces(p: &eval::Polygon, z: f64) -> Vec<Face> {
+    triangulate_polygon(p)
+        .iter()
+        .map(|t| Face::new(t.iter().map(|p| (p.0, p.1, z)).collect()))
+        .collect()
+}
+
+fn extrude_mesh(p: &eval::Polygon, e: &eval::Extrusion) -> Mesh {
+    let slices = if e.is_straight() { 1 } else { e.slices };
+    let at = |q: Point2, slice: usize| {
+        let t = slice as f64 / slices as f64;
+        let q = e.apply(q, t);
+        (q.0, q.1, e.bottom() + e.height * t)
+    };
+
+    let mut faces = Vec::new();
+
+    // Caps, leaving out the top if it is scaled to nothing
+    let has_top = e.top_scale.0 > 0.0 && e.top_scale.1 > 0.0;
+    for t in triangulate_polygon(p) {
+        push_face(&mut faces, t.iter().rev().map(|&q| at(q, 0)).collect());
+        if has_top {
+            push_face(&mut faces, t.iter().map(|&q| at(q, slices)).collect());
+        }
+    }
+
+    // Walls, with outlines going counter-clockwise and holes clockwise so that they face outwards.
+    // Twisting (or scaling x and y differently) bends them, so then they are split into triangles.
+    let planar = e.twist == 0.0 && e.top_scale.0 == e.top_scale.1;
+    for (points, _) in plane::oriented_rings(p) {
+        for (i, &a) in points.iter().enumerate() {
+            let b = points[(i + 1) % points.len()];
+            for slice in 0..slices {
+                let (a0, b0) = (at(a, slice), at(b, slice));
+                let (a1, b1) = (at(a, slice + 1), at(b, slice + 1));
+                if planar {
+                    push_face(&mut faces, vec![a0, b0, b1, a1]);
+                } else {
+                    push_face(&mut faces, vec![a0, b0, b1]);
+                    push_face(&mut faces, vec![a0, b1, a1]);
+                }
+            }
+        }
+    }
+
+    Mesh { faces, flat: false }
+}
+
+/// Adds a face without its repeated points (like the ones on an axis, or at the tip of a cone),
+/// unless too few are left for it to have an area
+fn push_face(faces: &mut Vec<Face>, mut points: Vec<Point3>) {
+    points.dedup();
+    while points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+
+    if points.len() >= 3 {
+        faces.push(Face::new(points));
+    }
+}
+
+/// Revolves a profile (with x as the distance from the z-axis) around the z-axis, by an angle in
+/// degrees. The number of segments is for a whole revolution.
+fn rotate_extrude_mesh(p: &eval::Polygon, angle: f64, segments: usize) -> Mesh {
+    let whole = angle.abs() >= 360.0;
+    let steps = ((segments as f64 * angle.abs() / 360.0).ceil() as usize).max(1);
+    let revolve = |q: Point2, step: usize| {
+        let a = (angle * step as f64 / steps as f64).to_radians();
+        snap((q.0 * a.cos(), q.0 * a.sin(), q.1))
+    };
+
+    let mut faces = Vec::new();
+
+    // Seen from the start of the revolution, outlines go counter-clockwise (like the caps), so the
+    // faces swept by their edges face outwards
+    for (points, _) in plane::oriented_rings(p) {
+        for (i, &a) in points.iter().enumerate() {
+            let b = points[(i + 1) % points.len()];
+            for step in 0..steps {
+                let next = if whole && step + 1 == steps {
+                    0
+                } else {
+                    step + 1
+                };
+                push_face(
+                    &mut faces,
+                    vec![
+                        revolve(a, step),
+                        revolve(a, next),
+                        revolve(b, next),
+                        revolve(b, step),
+                    ],
+                );
+            }
+        }
+    }
+
+    if !whole {
+        for t in triangulate_polygon(p) {
+            faces.push(Face::new(t.iter().map(|&q| revolve(q, 0)).collect()));
+            faces.push(Face::new(
+                t.iter().rev().map(|&q| revolve(q, steps)).collect(),
+            ));
+        }
+    }
+
+    // Revolving clockwise turns everything inside out
+    if angle < 0.0 {
+        for f in &mut faces {
+            f.points.reverse();
+        }
+    }
+
+    Mesh { faces, flat: false }
+}
+
+fn cube_mesh(size: Point3, center: bool) -> Mesh {
+    let (x0, y0) = if center {
+        (-size.0 / 2.0, -size.1 / 2.0)
+    } else {
+        (0.0, 0.0)
+    };
+    let (x1, y1) = (x0 + size.0, y0 + size.1);
+
+    let base = eval::Polygon(vec![(x0, y0), (x1, y0), (x1, y1), (x0, y1)], None, None);
+    extrude_mesh(
+        &base,
+        &eval::Extrusion {
+            height: size.2,
+            twist: 0.0,
+            top_scale: (1.0, 1.0),
+            slices: 1,
+            center,
+        },
+    )
+}
+
+/// A solid of revolution around the z-axis, through circles given as their radius and height
+/// (from the bottom up). Circles without a radius are single points, like the tip of a cone.
+fn revolved_mesh(circles: &[(f64, f64)], segments: usize) -> Mesh {
+    let rings: Vec<Vec<Point3>> = circles
+        .iter()
+        .map(|&(r, z)| {
+            if r == 0.0 {
+                vec![(0.0, 0.0, z)]
+            } else {
+                eval::circle_points(segments, r)
+                    .into_iter()
+                    .map(|p| (p.0, p.1, z))
+                    .collect()
+            }
+        })
+        .collect();
+
+    let mut faces = Vec::new();
+
+    // Caps
+    if let Some(bottom) = rings.first().filter(|r| r.len() > 1) {
+        faces.push(Face::new(bottom.iter().rev().copied().collect()));
+    }
+    if let Some(top) = rings.last().filter(|r| r.len() > 1) {
+        faces.push(Face::new(top.clone()));
+    }
+
+    // Walls, which are triangles where a circle is a single point
+    for pair in rings.windows(2) {
+        let (low, high) = (&pair[0], &pair[1]);
+        for i in 0..segments {
+            let j = (i + 1) % segments;
+            faces.push(Face::new(match (low.len(), high.len()) {
+                (1, _) => vec![low[0], high[j], high[i]],
+                (_, 1) => vec![low[i], low[j], high[0]],
+                _ => vec![low[i], low[j], high[j], high[i]],
+            }));
+        }
+    }
+
+    Mesh { faces, flat: false }
+}
+
+/// A sphere made of rings like OpenSCAD's, which don't reach the poles
+fn sphere_mesh(r: f64, segments: usize) -> Mesh {
+    let rings = segments.div_ceil(2);
+    let circles: Vec<(f64, f64)> = (0..rings)
+        .rev()
+        .map(|i| {
+            let phi = std::f64::consts::PI * (i as f64 + 0.5) / rings as f64;
+            (r * phi.sin(), r * phi.cos())
+        })
+        .collect();
+
+    revolved_mesh(&circles, segments)
+}
+
+/// A boolean operation on the faces of two solids
+type BooleanOp = fn(&[Face], &[Face]) -> Vec<Face>;
+
+/// Combines the meshes of the children using a boolean operation, which is only possible for
+/// solids
+fn combine(name: &str, children: &[eval::NativeValue], op: BooleanOp) -> Result<Mesh, String> {
+    let meshes = children
+        .iter()
+        .map(from_native)
+        .collect::<Result<Vec<Mesh>, String>>()?;
+
+    if meshes.iter().any(|m| m.flat) {
+        // 2D values are just drawn on top of each other
+        if name == "union" {
+            return Ok(Mesh {
+                faces: meshes.into_iter().flat_map(|m| m.faces).collect(),
+                flat: true,
+            });
+        }
+
+        return Err(format!(
+            "The {} of 2D values (like polygons) is not supported by this backend",
+            name
+        ));
+    }
+
+    let mut meshes = meshes.into_iter();
+    let first = meshes.next().unwrap_or_default();
+    Ok(meshes.fold(first, |acc, m| Mesh {
+        faces: op(&acc.faces, &m.faces),
+        flat: false,
+    }))
+}
+
+fn points_of(mesh: &Mesh) -> Vec<Point3> {
+    mesh.faces
+        .iter()
+        .flat_map(|f| f.points.iter().copied())
+        .collect()
+}
+
+/// Every point of `a` added to every point of `b`
+fn sums(a: &[Point3], b: &[Point3]) -> Vec<Point3> {
+    a.iter()
+        .flat_map(|&p| b.iter().map(move |&q| geom::add(p, q)))
+        .collect()
+}
+
+/// The convex hull of points in the xy-plane, as a single face
+fn flat_hull(points: &[Point3]) -> Vec<Face> {
+    let points: Vec<Point2> = points.iter().map(|p| (p.0, p.1)).collect();
+    let hull = hull::hull_2d(&points);
+    if hull.len() < 3 {
+        return Vec::new();
+    }
+
+    vec![Face::new(hull.iter().map(|p| (p.0, p.1, 0.0)).collect())]
+}
+
+/// If none of the points of a solid are in front of any of its faces
+fn is_convex(mesh: &Mesh) -> bool {
+    let points = points_of(mesh);
+    mesh.faces.iter().all(|f| {
+        let n = geom::polygon_normal(&f.points);
+        let n = geom::mul(n, 1.0 / geom::length(n));
+        points
+            .iter()
+            .all(|&p| geom::dot(n, geom::sub(p, f.points[0])) <= 1e-9)
+    })
+}
+
+/// If a flat mesh is convex, which is when its faces (that don't overlap, unless it is a union)
+/// cover as much area as their convex hull
+fn is_flat_convex(mesh: &Mesh) -> bool {
+    let area = |points: Vec<Point2>| plane::area(&points).abs();
+    let faces: f64 = mesh
+        .faces
+        .iter()
+        .map(|f| area(f.points.iter().map(|p| (p.0, p.1)).collect()))
+        .sum();
+    let hull = area(hull::hull_2d(
+        &points_of(mesh)
+            .iter()
+            .map(|p| (p.0, p.1))
+            .collect::<Vec<Point2>>(),
+    ));
+
+    (hull - faces).abs() <= 1e-9 * hull.max(1.0)
+}
+
+fn hull_mesh(children: &[eval::NativeValue]) -> Result<Mesh, String> {
+    let meshes = children
+        .iter()
+        .map(from_native)
+        .collect::<Result<Vec<Mesh>, String>>()?;
+    let points: Vec<Point3> = meshes.iter().flat_map(points_of).collect();
+
+    Ok(if meshes.iter().any(|m| m.flat) {
+        Mesh {
+            faces: flat_hull(&points),
+            flat: true,
+        }
+    } else {
+        Mesh {
+            faces: hull::hull_3d(&points),
+            flat: false,
+        }
+    })
+}
+
+fn minkowski_mesh(children: &[eval::NativeValue]) -> Result<Mesh, String> {
+    let mut meshes = children
+        .iter()
+        .map(from_native)
+        .collect::<Result<Vec<Mesh>, String>>()?
+        .into_iter();
+    let first = meshes.next().unwrap_or_default();
+
+    Ok(meshes.fold(first, |acc, m| minkowski(&acc, &m)))
+}
+
+/// The Minkowski sum of two meshes, as the union of convex pieces. Faces are convex, so the sum of
+/// two faces (or of a face and a convex mesh) is the convex hull of the sums of their points.
+fn minkowski(a: &Mesh, b: &Mesh) -> Mesh {
+    if a.faces.is_empty() || b.faces.is_empty() {
+        return Mesh {
+            faces: Vec::new(),
+            flat: a.flat,
+        };
+    }
+
+    // The faces of 2D values cover all of their area, so adding up the faces covers the sum
+    if a.flat || b.flat {
+        let pieces: Vec<Vec<Point3>> = if is_flat_convex(b) {
+            vec![points_of(b)]
+        } else {
+            b.faces.iter().map(|f| f.points.clone()).collect()
+        };
+
+        return Mesh {
+            faces: a
+                .faces
+                .iter()
+                .flat_map(|f| {
+                    pieces
+                        .iter()
+                        .flat_map(move |p| flat_hull(&sums(&f.points, p)))
+                })
+                .collect(),
+            flat: true,
+        };
+    }
+
+    // The faces of solids only cover their surface. Moving one solid by a point of the other
+    // fills in the rest, since everything in the sum that isn't covered by that is reached by
+    // moving the other solid along the surface of the first.
+    let (a, b) = if !is_convex(b) && is_convex(a) {
+        (b, a)
+    } else {
+        (a, b)
+    };
+    let moved = |m: &Mesh, by: Point3| m.clone().transformed(&eval::Transform::Translate(by));
+
+    let mut pieces = vec![moved(a, b.faces[0].points[0]).faces];
+    if is_convex(b) {
+        let points = points_of(b);
+        pieces.extend(
+            a.faces
+                .iter()
+                .map(|f| hull::hull_3d(&sums(&f.points, &points))),
+        );
+    } else {
+        pieces.push(moved(b, a.faces[0].points[0]).faces);
+        for f in &a.faces {
+            pieces.extend(
+                b.faces
+                    .iter()
+                    .map(|g| hull::hull_3d(&sums(&f.points, &g.points))),
+            );
+        }
+    }
+
+    let mut pieces = pieces.into_iter().filter(|p| !p.is_empty());
+    let first = pieces.next().unwrap_or_default();
+    Mesh {
+        faces: pieces.fold(first, |acc, p| csg::union(&acc, &p)),
+        flat: false,
+    }
+}
+
+pub fn from_native(native: &eval::NativeValue) -> Result<Mesh, String> {
+    Ok(match native {
+        eval::NativeValue::Polygon(p) => Mesh {
+            faces: polygon_faces(p, 0.0),
+            flat: true,
+        },
+        eval::NativeValue::Extrude(p, e) => extrude_mesh(p, e),
+        eval::NativeValue::RotateExtrude(p, angle, segments) => {
+            rotate_extrude_mesh(p, *angle, *segments)
+        }
+        eval::NativeValue::Cube(size, center) => cube_mesh(*size, *center),
+        eval::NativeValue::Sphere(r, segments) => sphere_mesh(*r, *segments),
+        eval::NativeValue::Cylinder(h, r1, r2, segments) => {
+            revolved_mesh(&[(*r1, 0.0), (*r2, *h)], *segments)
+        }
+        eval::NativeValue::Transform(t, children) => {
+            combine("union", children, csg::union)?.transformed(t)
+        }
+        // Leaving out the inside is only done for Minecraft, where it saves blocks
+        eval::NativeValue::Union(children) | eval::NativeValue::Shell(_, children) => {
+            combine("union", children, csg::union)?
+        }
+        eval::NativeValue::Hull(children) => hull_mesh(children)?,
+        eval::NativeValue::Minkowski(children) => minkowski_mesh(children)?,
+        eval::NativeValue::Material(m, children) => {
+            let mut mesh = combine("union", children, csg::union)?;
+            for f in mesh.faces.iter_mut().filter(|f| f.material.is_none()) {
+                f.material = Some(m.clone());
+            }
+            mesh
+        }
+        eval::NativeValue::Difference(children) => {
+            combine("difference", children, csg::difference)?
+        }
+        eval::NativeValue::Intersection(children) => {
+            combine("intersection", children, csg::intersection)?
+        }
+    })
+}
@@ -1,47 +1,127 @@
 use super::eval;
+pub use material::Materials;
+mod csg;
+mod geom;
+mod hull;
+mod material;
 mod mc;
+mod mesh;
+mod nbt;
+mod obj;
 mod scad;
+mod schematic;
+mod stl;
+mod structure;
 
-pub fn process_out_scad(input: &Vec<eval::EvaluatedValue>) -> String {
+pub fn process_out_scad(input: &[eval::EvaluatedValue], materials: &Materials) -> String {
     let mut scad_out = Vec::new();
 
-    for a in input.iter().filter(|x| match x {
-        eval::EvaluatedValue::Native(_) => true,
-        _ => false,
-    }) {
+    for a in input
+        .iter()
+        .filter(|x| matches!(x, eval::EvaluatedValue::Native(_)))
+    {
         scad_out.push(format!("// {:?}", a));
-        scad_out.push(scad::process_native(match a {
-            eval::EvaluatedValue::Native(n) => n,
-            _ => unreachable!(),
-        }));
+        scad_out.push(scad::process_native(
+            match a {
+                eval::EvaluatedValue::Native(n) => n,
+                _ => unreachable!(),
+            },
+            materials,
+        ));
     }
 
     scad_out.join("\n")
 }
 
 pub fn process_out_mc(
-    input: &Vec<eval::EvaluatedValue>,
+    input: &[eval::EvaluatedValue],
+    scale: i32,
+    origin: Option<(isize, isize, isize)>,
+    default_texture: &str,
+    materials: &Materials,
+) -> Result<(String, String), String> {
+    let mc_out = mc_blocks(input, scale, materials)?;
+
+    Ok((
+        mc::blocks_to_cmds(&mc_out, origin, materials.minecraft_block(default_texture)).join("\n"),
+        mc::blocks_to_destroys(&mc_out, origin).join("\n"),
+    ))
+}
+
+/// Writes the Minecraft build as a (gzip compressed) structure file, to load with a structure
+/// block
+pub fn process_out_structure(
+    input: &[eval::EvaluatedValue],
+    scale: i32,
+    default_texture: &str,
+    materials: &Materials,
+) -> Result<Vec<u8>, String> {
+    structure::blocks_to_structure(
+        &mc_blocks(input, scale, materials)?,
+        materials.minecraft_block(default_texture),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Writes the Minecraft build as a Sponge schematic, to paste with WorldEdit
+pub fn process_out_schematic(
+    input: &[eval::EvaluatedValue],
     scale: i32,
     origin: Option<(isize, isize, isize)>,
-) -> (String, String) {
+    default_texture: &str,
+    materials: &Materials,
+) -> Result<Vec<u8>, String> {
+    schematic::blocks_to_schematic(
+        &mc_blocks(input, scale, materials)?,
+        origin,
+        materials.minecraft_block(default_texture),
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// The blocks of the Minecraft build, with their materials turned into blocks
+fn mc_blocks(
+    input: &[eval::EvaluatedValue],
+    scale: i32,
+    materials: &Materials,
+) -> Result<Vec<mc::Block>, String> {
     let mut mc_out = Vec::new();
+    for n in natives(input) {
+        mc::process_native(n, scale, &mut mc_out)?;
+    }
 
-    for a in input.iter().filter(|x| match x {
-        eval::EvaluatedValue::Native(_) => true,
-        _ => false,
-    }) {
-        mc::process_native(
-            match a {
-                eval::EvaluatedValue::Native(n) => n,
-                _ => unreachable!(),
-            },
-            scale,
-            &mut mc_out,
-        );
+    for b in &mut mc_out {
+        if let Some(t) = &b.texture {
+            b.texture = Some(materials.minecraft_block(t).to_owned());
+        }
     }
 
-    (
-        mc::blocks_to_cmds(&mc_out, origin).join("\n"),
-        mc::blocks_to_destroys(&mc_out, origin).join("\n"),
-    )
+    Ok(mc_out)
+}
+
+/// Returns the OBJ file and the MTL file it refers to (by the name `mtllib`)
+pub fn process_out_obj(
+    input: &[eval::EvaluatedValue],
+    materials: &Materials,
+    mtllib: Option<&str>,
+) -> Result<(String, Option<String>), String> {
+    obj::process_natives(&natives(input), materials, mtllib)
+}
+
+pub fn process_out_stl(input: &[eval::EvaluatedValue], ascii: bool) -> Result<Vec<u8>, String> {
+    if ascii {
+        stl::process_natives_ascii(&natives(input)).map(String::into_bytes)
+    } else {
+        stl::process_natives_binary(&natives(input))
+    }
+}
+
+fn natives(input: &[eval::EvaluatedValue]) -> Vec<&eval::NativeValue> {
+    input
+        .iter()
+        .filter_map(|x| match x {
+            eval::EvaluatedValue::Native(n) => Some(n),
+            _ => None,
+        })
+        .collect()
 }
@@ -0,0 +1,221 @@
+// Minecraft's Named Binary Tag format, just enough of it to write structures and schematics (and
+// to read them back in tests): https://minecraft.wiki/w/NBT_format
+
+use std::io::{self, Write};
+
+#[cfg(test)]
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Short(i16),
+    Int(i32),
+    ByteArray(Vec<u8>),
+    String(String),
+    /// All of the tags in a list have the same type, an empty list is a list of End tags
+    List(Vec<Tag>),
+    Compound(Vec<(String, Tag)>),
+    IntArray(Vec<i32>),
+}
+
+impl Tag {
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+        }
+    }
+
+    fn write_payload(&self, out: &mut Vec<u8>) {
+        match self {
+            Tag::Short(v) => out.extend(&v.to_be_bytes()),
+            Tag::Int(v) => out.extend(&v.to_be_bytes()),
+            Tag::ByteArray(v) => {
+                out.extend(&(v.len() as i32).to_be_bytes());
+                out.extend(v);
+            }
+            Tag::String(s) => write_string(s, out),
+            Tag::List(tags) => {
+                out.push(tags.first().map(Tag::id).unwrap_or(0));
+                out.extend(&(tags.len() as i32).to_be_bytes());
+                for t in tags {
+                    t.write_payload(out);
+                }
+            }
+            Tag::Compound(tags) => {
+                for (name, t) in tags {
+                    out.push(t.id());
+                    write_string(name, out);
+                    t.write_payload(out);
+                }
+                // End tag
+                out.push(0);
+            }
+            Tag::IntArray(v) => {
+                out.extend(&(v.len() as i32).to_be_bytes());
+                for i in v {
+                    out.extend(&i.to_be_bytes());
+                }
+            }
+        }
+    }
+}
+
+/// Strings are stored as their length followed by (Java's modified) UTF-8, which is the same as
+/// normal UTF-8 for the names and block states we write.
+fn write_string(s: &str, out: &mut Vec<u8>) {
+    out.extend(&(s.len() as u16).to_be_bytes());
+    out.extend(s.as_bytes());
+}
+
+/// Writes a named root compound, gzip compressed like Minecraft expects its files to be
+pub fn write_gzip(name: &str, root: &Tag) -> io::Result<Vec<u8>> {
+    let mut out = vec![root.id()];
+    write_string(name, &mut out);
+    root.write_payload(&mut out);
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&out)?;
+    encoder.finish()
+}
+
+/// Splits a block state like `oak_stairs[facing=east,half=top]` into its namespaced name and
+/// properties
+pub fn parse_block_state(state: &str) -> (String, Vec<(String, String)>) {
+    let (name, properties) = match state.find('[') {
+        Some(i) => (&state[..i], state[i + 1..].trim_end_matches(']')),
+        None => (state, ""),
+    };
+
+    let name = if name.contains(':') {
+        name.to_owned()
+    } else {
+        format!("minecraft:{}", name)
+    };
+
+    let properties = properties
+        .split(',')
+        .filter_map(|p| {
+            let mut kv = p.splitn(2, '=');
+            match (kv.next(), kv.next()) {
+                (Some(k), Some(v)) => Some((k.trim().to_owned(), v.trim().to_owned())),
+                _ => None,
+            }
+        })
+        .collect();
+
+    (name, properties)
+}
+
+/// Reads a named root compound written by `write_gzip`. Only the tags that can be written can be
+/// read.
+#[cfg(test)]
+pub fn read_gzip(data: &[u8]) -> io::Result<(String, Tag)> {
+    use std::io::Read;
+
+    let mut bytes = Vec::new();
+    GzDecoder::new(data).read_to_end(&mut bytes)?;
+
+    let mut reader = Reader {
+        bytes: &bytes,
+        at: 0,
+    };
+    let id = reader.take(1)?[0];
+    let name = reader.string()?;
+    let root = reader.payload(id)?;
+    Ok((name, root))
+}
+
+#[cfg(test)]
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+#[cfg(test)]
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.at + n > self.bytes.len() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.at += n;
+        Ok(&self.bytes[self.at - n..self.at])
+    }
+
+    fn short(&mut self) -> io::Result<i16> {
+        let b = self.take(2)?;
+        Ok(i16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn int(&mut self) -> io::Result<i32> {
+        let b = self.take(4)?;
+        Ok(i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn length(&mut self) -> io::Result<usize> {
+        let n = self.int()?;
+        if n < 0 {
+            return Err(invalid(format!("Negative length {}", n)));
+        }
+        Ok(n as usize)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let n = self.short()? as u16 as usize;
+        String::from_utf8(self.take(n)?.to_vec()).map_err(|e| invalid(e.to_string()))
+    }
+
+    fn payload(&mut self, id: u8) -> io::Result<Tag> {
+        Ok(match id {
+            2 => Tag::Short(self.short()?),
+            3 => Tag::Int(self.int()?),
+            7 => {
+                let n = self.length()?;
+                Tag::ByteArray(self.take(n)?.to_vec())
+            }
+            8 => Tag::String(self.string()?),
+            9 => {
+                let id = self.take(1)?[0];
+                let n = self.length()?;
+                let mut tags = Vec::new();
+                for _ in 0..n {
+                    tags.push(self.payload(id)?);
+                }
+                Tag::List(tags)
+            }
+            10 => {
+                let mut tags = Vec::new();
+                loop {
+                    let id = self.take(1)?[0];
+                    if id == 0 {
+                        break;
+                    }
+                    let name = self.string()?;
+                    tags.push((name, self.payload(id)?));
+                }
+                Tag::Compound(tags)
+            }
+            11 => {
+                let n = self.length()?;
+                let mut v = Vec::new();
+                for _ in 0..n {
+                    v.push(self.int()?);
+                }
+                Tag::IntArray(v)
+            }
+            id => return Err(invalid(format!("Unsupported tag type {}", id))),
+        })
+    }
+}
+
+#[cfg(test)]
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use super::eval;
+use super::geom::Point3;
+use super::material::Materials;
+use super::mesh;
+
+/// Writes the values as a Wavefront OBJ file, with a group for every value, and the MTL file
+/// with the materials it uses (if it uses any). `mtllib` is the name the OBJ file refers to the
+/// MTL file by.
+pub fn process_natives(
+    natives: &[&eval::NativeValue],
+    materials: &Materials,
+    mtllib: Option<&str>,
+) -> Result<(String, Option<String>), String> {
+    let mut vertices = Vec::new();
+    let mut groups = Vec::new();
+    let mut used_materials: Vec<String> = Vec::new();
+
+    // Points are shared between faces, so only write every point once
+    let mut indices: HashMap<(u64, u64, u64), usize> = HashMap::new();
+    let mut index_of = |p: Point3| {
+        // Adding 0.0 turns -0.0 into 0.0, so that they are the same point
+        let key = (
+            (p.0 + 0.0).to_bits(),
+            (p.1 + 0.0).to_bits(),
+            (p.2 + 0.0).to_bits(),
+        );
+        *indices.entry(key).or_insert_with(|| {
+            vertices.push(format!("v {} {} {}", p.0, p.1, p.2));
+            vertices.len()
+        })
+    };
+
+    for (i, native) in natives.iter().enumerate() {
+        let mut group = vec![format!("g {}_{}", native.name(), i)];
+        let mut current = None;
+        for (t, material) in mesh::from_native(native)?.triangles() {
+            if material != current {
+                // Faces without a material go back to the default one
+                group.push(match material {
+                    Some(m) => format!("usemtl {}", materials.mtl_name(m)),
+                    None => "usemtl default".to_owned(),
+                });
+                if let Some(m) = material {
+                    if !used_materials.iter().any(|u| u == m) {
+                        used_materials.push(m.to_owned());
+                    }
+                }
+                current = material;
+            }
+
+            let (a, b, c) = (index_of(t[0]), index_of(t[1]), index_of(t[2]));
+            if a != b && b != c && c != a {
+                group.push(format!("f {} {} {}", a, b, c));
+            }
+        }
+        groups.push(group.join("\n"));
+    }
+
+    let mut out = vec!["# Generated by cap".to_owned()];
+    if let Some(mtllib) = mtllib.filter(|_| !used_materials.is_empty()) {
+        out.push(format!("mtllib {}", mtllib));
+    }
+    out.extend(vertices);
+    out.extend(groups);
+
+    if used_materials.is_empty() {
+        return Ok((out.join("\n"), None));
+    }
+
+    // Unknown colours are light grey, like most viewers show faces without a material
+    let grey = (0.8, 0.8, 0.8, 1.0);
+    let mut mtl = vec!["# Generated by cap".to_owned()];
+    let entries = std::iter::once(("default".to_owned(), grey)).chain(
+        used_materials
+            .iter()
+            .map(|m| (materials.mtl_name(m), materials.color(m).unwrap_or(grey))),
+    );
+    for (name, (r, g, b, a)) in entries {
+        mtl.push(format!("newmtl {}", name));
+        mtl.push(format!("Kd {} {} {}", r, g, b));
+        mtl.push(format!("d {}", a));
+    }
+
+    Ok((out.join("\n"), Some(mtl.join("\n"))))
+}
@@ -1,28 +1,122 @@
 use super::eval;
+use super::material::Materials;
 
-pub fn process_native(native: &eval::NativeValue) -> String {
-    let process_polygon = |p: &eval::Polygon| {
-        format!(
-            "translate([{},{},{}]) polygon(points=[{}]);",
-            (p.2).0,
-            (p.2).1,
-            (p.2).2,
-            p.0.iter()
-                .map(|x| format!("[{}, {}]", x.0, x.1))
-                .collect::<Vec<String>>()
-                .join(", ")
-        )
-    };
-
+pub fn process_native(native: &eval::NativeValue, materials: &Materials) -> String {
     match native {
         eval::NativeValue::Polygon(p) => process_polygon(p),
-        eval::NativeValue::Extrude(p, h) => {
+        eval::NativeValue::Extrude(p, e) => {
+            let (sx, sy) = e.top_scale;
             format!(
-                "translate([0, 0, {translation_z}]) linear_extrude(height = {height}, center = false, twist = 0, scale = 1.0) {body}",
-                translation_z = (p.2).2,
-                height = h,
+                "linear_extrude(height = {height}, center = {center}, twist = {twist}, scale = {scale}, slices = {slices}) {body}",
+                height = e.height,
+                center = e.center,
+                twist = e.twist,
+                scale = if sx == sy {
+                    format!("{}", sx)
+                } else {
+                    format!("[{}, {}]", sx, sy)
+                },
+                slices = e.slices,
                 body = process_polygon(p)
             )
         }
+        eval::NativeValue::RotateExtrude(p, angle, segments) => format!(
+            "rotate_extrude(angle = {}, $fn = {}) {}",
+            angle,
+            segments,
+            process_polygon(p)
+        ),
+        eval::NativeValue::Cube(size, center) => format!(
+            "cube([{}, {}, {}], center = {});",
+            size.0, size.1, size.2, center
+        ),
+        eval::NativeValue::Sphere(r, segments) => format!("sphere(r = {}, $fn = {});", r, segments),
+        eval::NativeValue::Cylinder(h, r1, r2, segments) => format!(
+            "cylinder(h = {}, r1 = {}, r2 = {}, center = false, $fn = {});",
+            h, r1, r2, segments
+        ),
+        eval::NativeValue::Transform(t, children) => process_block(
+            &match t {
+                eval::Transform::Translate(o) => format!("translate([{}, {}, {}])", o.0, o.1, o.2),
+                eval::Transform::Rotate(a) => format!("rotate([{}, {}, {}])", a.0, a.1, a.2),
+                eval::Transform::Scale(f) => format!("scale([{}, {}, {}])", f.0, f.1, f.2),
+            },
+            children,
+            materials,
+        ),
+        eval::NativeValue::Union(children) => process_block("union()", children, materials),
+        eval::NativeValue::Difference(children) => {
+            process_block("difference()", children, materials)
+        }
+        eval::NativeValue::Intersection(children) => {
+            process_block("intersection()", children, materials)
+        }
+        eval::NativeValue::Hull(children) => process_block("hull()", children, materials),
+        eval::NativeValue::Minkowski(children) => process_block("minkowski()", children, materials),
+        // OpenSCAD has no blocks to leave out, so the values are drawn solid
+        eval::NativeValue::Shell(_, children) => process_block("union()", children, materials),
+        eval::NativeValue::Material(m, children) => process_block(
+            &format!("color({})", materials.scad_color(m)),
+            children,
+            materials,
+        ),
+    }
+}
+
+fn process_polygon(p: &eval::Polygon) -> String {
+    let points =
+        p.0.iter()
+            .map(|x| format!("[{}, {}]", x.0, x.1))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+    match (&p.2, &p.1) {
+        (Some(eval::Shape::Circle(r, segments)), _) => {
+            format!("circle(r = {}, $fn = {});", r, segments)
+        }
+        (Some(eval::Shape::Square(size, center)), _) => {
+            format!("square([{}, {}], center = {});", size.0, size.1, center)
+        }
+        // OpenSCAD draws regular polygons as circles with as many segments as sides
+        (Some(eval::Shape::Ngon(sides, r)), _) => {
+            format!("circle(r = {}, $fn = {});", r, sides)
+        }
+        (Some(eval::Shape::Offset(source, delta, join)), _) => format!(
+            "{} {}",
+            match join {
+                eval::Join::Round(segments) => format!("offset(r = {}, $fn = {})", delta, segments),
+                eval::Join::Miter => format!("offset(delta = {})", delta),
+                eval::Join::Chamfer => format!("offset(delta = {}, chamfer = true)", delta),
+            },
+            process_polygon(source)
+        ),
+        (None, None) => format!("polygon(points=[{}]);", points),
+        (None, Some(paths)) => format!(
+            "polygon(points=[{}], paths=[{}]);",
+            points,
+            paths
+                .iter()
+                .map(|path| format!(
+                    "[{}]",
+                    path.iter()
+                        .map(|i| i.to_string())
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
     }
 }
+
+fn process_block(header: &str, children: &[eval::NativeValue], materials: &Materials) -> String {
+    format!(
+        "{} {{\n{}\n}}",
+        header,
+        children
+            .iter()
+            .map(|c| process_native(c, materials))
+            .collect::<Vec<String>>()
+            .join("\n")
+    )
+}
@@ -0,0 +1,194 @@
+// Sponge schematics (version 2), as read by WorldEdit:
+// https://github.com/SpongePowered/Schematic-Specification/blob/master/versions/schematic-2.md
+
+use std::collections::HashMap;
+use std::io;
+
+use super::mc::{self, Block, McPos};
+use super::nbt::{self, Tag};
+
+const AIR: &str = "minecraft:air";
+
+/// Block states in the palette are written like `minecraft:oak_stairs[facing=east]`
+fn palette_name(state: &str) -> String {
+    let (name, properties) = nbt::parse_block_state(state);
+    if properties.is_empty() {
+        return name;
+    }
+
+    let properties: Vec<String> = properties
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect();
+    format!("{}[{}]", name, properties.join(","))
+}
+
+/// Palette indices are stored as varints: 7 bits at a time, with the high bit set on every byte
+/// but the last
+fn write_varint(mut v: u32, out: &mut Vec<u8>) {
+    while v >= 0x80 {
+        out.push((v & 0x7f) as u8 | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn dimension(name: &str, v: i32) -> io::Result<i16> {
+    if v > i32::from(u16::MAX) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("The build is too large for a schematic ({} is {})", name, v),
+        ));
+    }
+
+    // Stored as unsigned shorts
+    Ok(v as u16 as i16)
+}
+
+pub fn blocks_to_schematic(
+    blocks: &[Block],
+    origin: Option<(isize, isize, isize)>,
+    default_texture: &str,
+) -> io::Result<Vec<u8>> {
+    let origin = origin.unwrap_or_default();
+    let origin: McPos = (origin.0 as i32, origin.2 as i32, origin.1 as i32);
+    let (min, size) = mc::mc_area(blocks);
+    let width = dimension("width", size.0)?;
+    let height = dimension("height", size.1)?;
+    let length = dimension("length", size.2)?;
+    let size = (size.0 as usize, size.1 as usize, size.2 as usize);
+
+    let mut palette = vec![(AIR.to_owned(), Tag::Int(0))];
+    let mut palette_indices: HashMap<String, u32> = HashMap::new();
+    palette_indices.insert(AIR.to_owned(), 0);
+
+    // Indexed by x + z * width + y * width * length, empty space is air
+    let mut indices = vec![0; size.0 * size.1 * size.2];
+    for b in blocks {
+        let name = palette_name(b.texture.as_deref().unwrap_or(default_texture));
+        let next = palette.len() as u32;
+        let index = *palette_indices.entry(name.clone()).or_insert_with(|| {
+            palette.push((name, Tag::Int(next as i32)));
+            next
+        });
+
+        let p = mc::relative_to(mc::mc_pos(b), min);
+        let p = (p.0 as usize, p.1 as usize, p.2 as usize);
+        indices[p.0 + p.2 * size.0 + p.1 * size.0 * size.2] = index;
+    }
+
+    let mut block_data = Vec::new();
+    for i in indices {
+        write_varint(i, &mut block_data);
+    }
+
+    // The offset is where the lowest corner was in the world, while WorldEdit's own offset is
+    // where that corner is relative to the origin (so that pasting puts the origin where the
+    // player is standing)
+    let offset = (min.0 + origin.0, min.1 + origin.1, min.2 + origin.2);
+    let metadata = Tag::Compound(vec![
+        ("WEOffsetX".to_owned(), Tag::Int(min.0)),
+        ("WEOffsetY".to_owned(), Tag::Int(min.1)),
+        ("WEOffsetZ".to_owned(), Tag::Int(min.2)),
+    ]);
+
+    let root = Tag::Compound(vec![
+        ("Version".to_owned(), Tag::Int(2)),
+        ("DataVersion".to_owned(), Tag::Int(mc::DATA_VERSION)),
+        ("Metadata".to_owned(), metadata),
+        ("Width".to_owned(), Tag::Short(width)),
+        ("Height".to_owned(), Tag::Short(height)),
+        ("Length".to_owned(), Tag::Short(length)),
+        (
+            "Offset".to_owned(),
+            Tag::IntArray(vec![offset.0, offset.1, offset.2]),
+        ),
+        ("PaletteMax".to_owned(), Tag::Int(palette.len() as i32)),
+        ("Palette".to_owned(), Tag::Compound(palette)),
+        ("BlockData".to_owned(), Tag::ByteArray(block_data)),
+    ]);
+
+    nbt::write_gzip("Schematic", &root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(pos: (isize, isize, isize), texture: Option<&str>) -> Block {
+        Block {
+            pos,
+            texture: texture.map(str::to_owned),
+        }
+    }
+
+    fn get<'a>(tag: &'a Tag, name: &str) -> &'a Tag {
+        match tag {
+            Tag::Compound(tags) => &tags.iter().find(|(n, _)| n == name).unwrap().1,
+            t => panic!("expected a compound, got {:?}", t),
+        }
+    }
+
+    fn short(tag: &Tag) -> i16 {
+        match tag {
+            Tag::Short(v) => *v,
+            t => panic!("expected a short, got {:?}", t),
+        }
+    }
+
+    fn int(tag: &Tag) -> i32 {
+        match tag {
+            Tag::Int(v) => *v,
+            t => panic!("expected an int, got {:?}", t),
+        }
+    }
+
+    #[test]
+    fn reads_back_the_blocks_written() {
+        let blocks = vec![
+            block((0, 0, 0), Some("stone")),
+            block((2, 1, 0), Some("oak_stairs[facing=east,half=top]")),
+            block((0, 0, 3), None),
+        ];
+
+        let data = blocks_to_schematic(&blocks, None, "birch_planks").unwrap();
+        let (name, root) = nbt::read_gzip(&data).unwrap();
+        assert_eq!(name, "Schematic");
+
+        // Minecraft's y is up, which is z in the model
+        let size = (
+            short(get(&root, "Width")),
+            short(get(&root, "Height")),
+            short(get(&root, "Length")),
+        );
+        assert_eq!(size, (3, 4, 2));
+
+        let mut palette: Vec<(String, i32)> = match get(&root, "Palette") {
+            Tag::Compound(tags) => tags.iter().map(|(n, t)| (n.clone(), int(t))).collect(),
+            t => panic!("expected a compound, got {:?}", t),
+        };
+        palette.sort_by_key(|&(_, i)| i);
+        let palette: Vec<&str> = palette.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(
+            palette,
+            vec![
+                "minecraft:air",
+                "minecraft:stone",
+                "minecraft:oak_stairs[facing=east,half=top]",
+                "minecraft:birch_planks",
+            ]
+        );
+        assert_eq!(int(get(&root, "PaletteMax")), 4);
+
+        // Every index fits in one byte, at x + z * width + y * width * length
+        let data = match get(&root, "BlockData") {
+            Tag::ByteArray(data) => data,
+            t => panic!("expected a byte array, got {:?}", t),
+        };
+        let mut expected = vec![0; 24];
+        expected[0] = 1;
+        expected[2 + 3] = 2;
+        expected[3 * 3 * 2] = 3;
+        assert_eq!(data, &expected);
+    }
+}
@@ -0,0 +1,125 @@
+use super::eval;
+use super::geom::{self, Point3};
+use super::mesh;
+
+/// The triangles of all of the values, which have to be solids since STL files can't hold 2D
+/// shapes
+fn triangles(natives: &[&eval::NativeValue]) -> Result<Vec<[Point3; 3]>, String> {
+    let mut out = Vec::new();
+    for native in natives {
+        let mesh = mesh::from_native(native)?;
+        if mesh.flat {
+            return Err(format!(
+                "A {} is a 2D value, which can't be written to an STL file (extrude it first)",
+                native.name()
+            ));
+        }
+        out.extend(mesh.triangles().into_iter().map(|(t, _)| t));
+    }
+
+    Ok(out)
+}
+
+/// The outward facing unit normal of a counter-clockwise triangle
+fn normal(t: &[Point3; 3]) -> Point3 {
+    let n = geom::cross(geom::sub(t[1], t[0]), geom::sub(t[2], t[0]));
+    let length = geom::length(n);
+    if length == 0.0 {
+        (0.0, 0.0, 0.0)
+    } else {
+        geom::mul(n, 1.0 / length)
+    }
+}
+
+pub fn process_natives_ascii(natives: &[&eval::NativeValue]) -> Result<String, String> {
+    let mut out = vec!["solid cap".to_owned()];
+    for t in triangles(natives)? {
+        let n = normal(&t);
+        out.push(format!("  facet normal {} {} {}", n.0, n.1, n.2));
+        out.push("    outer loop".to_owned());
+        for p in &t {
+            out.push(format!("      vertex {} {} {}", p.0, p.1, p.2));
+        }
+        out.push("    endloop".to_owned());
+        out.push("  endfacet".to_owned());
+    }
+    out.push("endsolid cap".to_owned());
+
+    Ok(out.join("\n"))
+}
+
+pub fn process_natives_binary(natives: &[&eval::NativeValue]) -> Result<Vec<u8>, String> {
+    let triangles = triangles(natives)?;
+
+    // An 80 byte header (which mustn't start with "solid"), then the number of triangles
+    let mut out = b"Generated by cap".to_vec();
+    out.resize(80, 0);
+    out.extend(&(triangles.len() as u32).to_le_bytes());
+
+    for t in &triangles {
+        for p in [normal(t)].iter().chain(t.iter()) {
+            out.extend(&(p.0 as f32).to_le_bytes());
+            out.extend(&(p.1 as f32).to_le_bytes());
+            out.extend(&(p.2 as f32).to_le_bytes());
+        }
+        // Attribute byte count, which is unused
+        out.extend(&0u16.to_le_bytes());
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Checks that every edge of every triangle is matched by an edge going the other way, which
+    /// makes the triangles a closed surface without holes or flipped faces
+    fn assert_closed(native: &eval::NativeValue) {
+        let key = |p: Point3| (p.0.to_bits(), p.1.to_bits(), p.2.to_bits());
+
+        let mut edges = HashMap::new();
+        for (t, _) in mesh::from_native(native).unwrap().triangles() {
+            for i in 0..3 {
+                *edges.entry((key(t[i]), key(t[(i + 1) % 3]))).or_insert(0) += 1;
+            }
+        }
+
+        assert!(!edges.is_empty());
+        for ((a, b), n) in &edges {
+            assert_eq!(edges.get(&(*b, *a)), Some(n), "unmatched edge {:?}", (a, b));
+        }
+    }
+
+    fn cube(size: f64) -> eval::NativeValue {
+        eval::NativeValue::Cube((size, size, size), false)
+    }
+
+    #[test]
+    fn cube_is_closed() {
+        assert_closed(&cube(1.0));
+    }
+
+    #[test]
+    fn sphere_is_closed() {
+        assert_closed(&eval::NativeValue::Sphere(1.0, 12));
+    }
+
+    #[test]
+    fn difference_is_closed() {
+        let hole = eval::NativeValue::Transform(
+            eval::Transform::Translate((0.5, 0.5, -1.0)),
+            vec![eval::NativeValue::Cube((1.0, 1.0, 4.0), false)],
+        );
+        assert_closed(&eval::NativeValue::Difference(vec![cube(2.0), hole]));
+    }
+
+    #[test]
+    fn minkowski_is_closed() {
+        assert_closed(&eval::NativeValue::Minkowski(vec![
+            cube(2.0),
+            eval::NativeValue::Sphere(0.5, 8),
+        ]));
+    }
+}
@@ -0,0 +1,176 @@
+// Structure files, as saved by structure blocks: https://minecraft.wiki/w/Structure_file
+
+use std::collections::HashMap;
+use std::io;
+
+use super::mc::{self, Block, McPos};
+use super::nbt::{self, Tag};
+
+fn int_list(v: McPos) -> Tag {
+    Tag::List(vec![Tag::Int(v.0), Tag::Int(v.1), Tag::Int(v.2)])
+}
+
+fn palette_entry(state: &str) -> Tag {
+    let (name, properties) = nbt::parse_block_state(state);
+
+    let mut entry = vec![("Name".to_owned(), Tag::String(name))];
+    if !properties.is_empty() {
+        entry.push((
+            "Properties".to_owned(),
+            Tag::Compound(
+                properties
+                    .into_iter()
+                    .map(|(k, v)| (k, Tag::String(v)))
+                    .collect(),
+            ),
+        ));
+    }
+
+    Tag::Compound(entry)
+}
+
+pub fn blocks_to_structure(blocks: &[Block], default_texture: &str) -> io::Result<Vec<u8>> {
+    let (min, size) = mc::mc_area(blocks);
+
+    // Like in a schematic, a later block at the same position replaces the earlier one
+    let mut positions = Vec::new();
+    let mut states: HashMap<McPos, &str> = HashMap::new();
+    for b in blocks {
+        let pos = mc::relative_to(mc::mc_pos(b), min);
+        let state = b.texture.as_deref().unwrap_or(default_texture);
+        if states.insert(pos, state).is_none() {
+            positions.push(pos);
+        }
+    }
+
+    let mut palette = Vec::new();
+    let mut palette_indices: HashMap<&str, i32> = HashMap::new();
+    let mut block_tags = Vec::new();
+
+    for pos in positions {
+        let state = states[&pos];
+        let index = *palette_indices.entry(state).or_insert_with(|| {
+            palette.push(palette_entry(state));
+            palette.len() as i32 - 1
+        });
+
+        block_tags.push(Tag::Compound(vec![
+            ("state".to_owned(), Tag::Int(index)),
+            ("pos".to_owned(), int_list(pos)),
+        ]));
+    }
+
+    let root = Tag::Compound(vec![
+        ("DataVersion".to_owned(), Tag::Int(mc::DATA_VERSION)),
+        ("size".to_owned(), int_list(size)),
+        ("palette".to_owned(), Tag::List(palette)),
+        ("blocks".to_owned(), Tag::List(block_tags)),
+        ("entities".to_owned(), Tag::List(Vec::new())),
+    ]);
+
+    nbt::write_gzip("", &root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(pos: (isize, isize, isize), texture: Option<&str>) -> Block {
+        Block {
+            pos,
+            texture: texture.map(str::to_owned),
+        }
+    }
+
+    fn get<'a>(tag: &'a Tag, name: &str) -> &'a Tag {
+        match tag {
+            Tag::Compound(tags) => &tags.iter().find(|(n, _)| n == name).unwrap().1,
+            t => panic!("expected a compound, got {:?}", t),
+        }
+    }
+
+    fn list(tag: &Tag) -> &[Tag] {
+        match tag {
+            Tag::List(tags) => tags,
+            t => panic!("expected a list, got {:?}", t),
+        }
+    }
+
+    fn int(tag: &Tag) -> i32 {
+        match tag {
+            Tag::Int(v) => *v,
+            t => panic!("expected an int, got {:?}", t),
+        }
+    }
+
+    fn string(tag: &Tag) -> &str {
+        match tag {
+            Tag::String(s) => s,
+            t => panic!("expected a string, got {:?}", t),
+        }
+    }
+
+    /// The block states in a palette, written back like `oak_stairs[facing=east]`
+    fn states(palette: &[Tag]) -> Vec<String> {
+        palette
+            .iter()
+            .map(|entry| {
+                let name = string(get(entry, "Name")).trim_start_matches("minecraft:");
+                match entry {
+                    Tag::Compound(tags) if tags.len() > 1 => {
+                        let properties: Vec<String> = match get(entry, "Properties") {
+                            Tag::Compound(p) => p
+                                .iter()
+                                .map(|(k, v)| format!("{}={}", k, string(v)))
+                                .collect(),
+                            t => panic!("expected a compound, got {:?}", t),
+                        };
+                        format!("{}[{}]", name, properties.join(","))
+                    }
+                    _ => name.to_owned(),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_back_the_blocks_written() {
+        let blocks = vec![
+            block((0, 0, 0), None),
+            block((2, 1, 0), Some("oak_stairs[facing=east,half=top]")),
+            block((0, 0, 3), Some("stone")),
+            // Replaces the first block
+            block((0, 0, 0), Some("stone")),
+        ];
+
+        let data = blocks_to_structure(&blocks, "birch_planks").unwrap();
+        let (name, root) = nbt::read_gzip(&data).unwrap();
+        assert_eq!(name, "");
+
+        // Minecraft's y is up, which is z in the model
+        let size: Vec<i32> = list(get(&root, "size")).iter().map(int).collect();
+        assert_eq!(size, vec![3, 4, 2]);
+
+        let palette = states(list(get(&root, "palette")));
+        let mut read: Vec<((i32, i32, i32), String)> = list(get(&root, "blocks"))
+            .iter()
+            .map(|b| {
+                let pos: Vec<i32> = list(get(b, "pos")).iter().map(int).collect();
+                (
+                    (pos[0], pos[1], pos[2]),
+                    palette[int(get(b, "state")) as usize].clone(),
+                )
+            })
+            .collect();
+        read.sort();
+
+        assert_eq!(
+            read,
+            vec![
+                ((0, 0, 0), "stone".to_owned()),
+                ((0, 3, 0), "stone".to_owned()),
+                ((2, 0, 1), "oak_stairs[facing=east,half=top]".to_owned()),
+            ]
+        );
+    }
+}
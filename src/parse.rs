@@ -1,49 +1,84 @@
-use super::lex;
+use std::fmt;
+
+use super::lex::{self, Span, TokenKind};
 
 #[derive(Debug)]
 pub enum ParseError {
-    EOF(usize),
-    UnexpectedToken(lex::InputToken, usize),
+    /// The file ended before the expression starting at this paren was closed
+    MissingEndParen(Span),
+    UnexpectedToken(lex::InputToken),
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::MissingEndParen(span) => *span,
+            ParseError::UnexpectedToken(token) => token.span,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::MissingEndParen(_) => write!(f, "Missing end paren"),
+            ParseError::UnexpectedToken(token) => write!(f, "Unexpected token {:?}", token.kind),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Value {
-    Number(f64),
-    Identifier(String),
+    Number(f64, Span),
+    Identifier(String, Span),
     SExpression(SExpression),
 }
 
+impl Value {
+    pub fn span(&self) -> Span {
+        match self {
+            Value::Number(_, span) => *span,
+            Value::Identifier(_, span) => *span,
+            Value::SExpression(sexpr) => sexpr.span(),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct SExpression {
     body: Vec<Value>,
+    span: Span,
 }
 
 impl SExpression {
-    pub fn new(body: Vec<Value>) -> Self {
-        Self { body }
+    pub fn new(body: Vec<Value>, span: Span) -> Self {
+        Self { body, span }
     }
 
     pub fn first(&self) -> Option<&Value> {
         self.body.first()
     }
 
-    pub fn is_first(&self, comp: &Value) -> Option<bool> {
-        let first = self.body.first()?;
-        Some(first == comp)
+    pub fn is_first_identifier(&self, name: &str) -> bool {
+        match self.body.first() {
+            Some(Value::Identifier(id, _)) => id == name,
+            _ => false,
+        }
     }
 
     pub fn body(&self) -> &Vec<Value> {
         &self.body
     }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
 }
 
 pub fn parse(input: Vec<lex::InputToken>) -> Result<Vec<Value>, ParseError> {
     let input: Vec<lex::InputToken> = input
         .into_iter()
-        .filter(|x| match x {
-            lex::InputToken::Comment(_) => false,
-            _ => true,
-        })
+        .filter(|x| !matches!(x.kind, TokenKind::Comment(_)))
         .collect();
 
     let mut cur_index = 0;
@@ -66,54 +101,40 @@ pub fn parse(input: Vec<lex::InputToken>) -> Result<Vec<Value>, ParseError> {
 }
 
 fn parse_sexp(input: &[lex::InputToken], index: usize) -> Result<(SExpression, usize), ParseError> {
-    match input.get(index + 0) {
-        None => return Err(ParseError::EOF(2)),
-        Some(lex::InputToken::StartParen) => {}
-        Some(t) => return Err(ParseError::UnexpectedToken(t.clone(), index + 0)),
-    }
-
-    if input.get(index + 1) == Some(&lex::InputToken::EndParen) {
-        return Ok((SExpression::new(Vec::new()), index + 2));
-        // todo!("Return nil value?");
-    }
+    let start = match input.get(index) {
+        None => unreachable!("parse_sexp is only called on a StartParen"),
+        Some(t) if t.kind == TokenKind::StartParen => t.span,
+        Some(t) => return Err(ParseError::UnexpectedToken(t.clone())),
+    };
 
     let mut body = Vec::new();
     let mut cur_index = index + 1;
-    //TODO Parse value
     loop {
-        let (value, new_index) = parse_value(&input, cur_index)?;
-        body.push(value);
-
-        cur_index = new_index;
-        if input.get(cur_index + 0) == None {
-            eprintln!("Missing end paren");
-            return Err(ParseError::EOF(1));
-        }
-        if input.get(cur_index + 0) == Some(&lex::InputToken::EndParen) {
-            cur_index += 1;
-            break;
+        match input.get(cur_index) {
+            None => return Err(ParseError::MissingEndParen(start)),
+            Some(t) if t.kind == TokenKind::EndParen => {
+                let sexp = SExpression::new(body, start.to(t.span));
+                return Ok((sexp, cur_index + 1));
+            }
+            Some(_) => {
+                let (value, new_index) = parse_value(input, cur_index)?;
+                body.push(value);
+                cur_index = new_index;
+            }
         }
     }
-
-    let sexp = SExpression::new(body);
-    return Ok((sexp, cur_index));
 }
 
 fn parse_value(input: &[lex::InputToken], index: usize) -> Result<(Value, usize), ParseError> {
-    match &input.get(index + 0) {
-        None => Err(ParseError::EOF(0)),
-        Some(v) => match v {
-            lex::InputToken::Number(v) => Ok((Value::Number(*v), index + 1)),
-            lex::InputToken::Identifier(v) => Ok((Value::Identifier(v.to_owned()), index + 1)),
-            lex::InputToken::StartParen => {
-                let (expr, new_index) = parse_sexp(input, index)?;
-                Ok((Value::SExpression(expr), new_index))
-            }
-            lex::InputToken::Comment(_) => unreachable!(),
-            lex::InputToken::EndParen => Err(ParseError::UnexpectedToken(
-                lex::InputToken::EndParen,
-                index + 0,
-            )),
-        },
+    let token = &input[index];
+    match &token.kind {
+        TokenKind::Number(v) => Ok((Value::Number(*v, token.span), index + 1)),
+        TokenKind::Identifier(v) => Ok((Value::Identifier(v.to_owned(), token.span), index + 1)),
+        TokenKind::StartParen => {
+            let (expr, new_index) = parse_sexp(input, index)?;
+            Ok((Value::SExpression(expr), new_index))
+        }
+        TokenKind::Comment(_) => unreachable!(),
+        TokenKind::EndParen => Err(ParseError::UnexpectedToken(token.clone())),
     }
 }